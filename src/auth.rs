@@ -0,0 +1,57 @@
+use color_eyre::{eyre::eyre, Result};
+use std::env;
+
+/// Keyring service name the OpenAI key is stored under.
+const SERVICE: &str = "autocommit";
+const OPENAI_USER: &str = "openai-api-key";
+
+fn openai_entry() -> Result<keyring::Entry> {
+    Ok(keyring::Entry::new(SERVICE, OPENAI_USER)?)
+}
+
+/// Store the OpenAI key in the OS keyring (macOS Keychain, Secret Service or
+/// Windows Credential Manager).
+pub fn set(key: &str) -> Result<()> {
+    if key.trim().is_empty() {
+        return Err(eyre!("The key is empty"));
+    }
+    openai_entry()?.set_password(key.trim())?;
+    Ok(())
+}
+
+/// Remove the OpenAI key from the keyring, returning whether there was one.
+pub fn unset() -> Result<bool> {
+    match openai_entry()?.delete_password() {
+        Ok(()) => Ok(true),
+        Err(keyring::Error::NoEntry) => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Whether the keyring has an OpenAI key.
+pub fn is_set() -> Result<bool> {
+    match openai_entry()?.get_password() {
+        Ok(_) => Ok(true),
+        Err(keyring::Error::NoEntry) => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// The OpenAI key, from `OPENAI_API_KEY` if set, otherwise from the keyring.
+pub fn openai_api_key() -> Result<String> {
+    if let Ok(key) = env::var("OPENAI_API_KEY") {
+        if !key.is_empty() {
+            return Ok(key);
+        }
+    }
+    match openai_entry()?.get_password() {
+        Ok(key) => Ok(key),
+        Err(keyring::Error::NoEntry) => Err(eyre!(
+            "No OpenAI key found, set OPENAI_API_KEY or run `autocommit auth set`"
+        )),
+        Err(e) => Err(eyre!(
+            "Could not read the OpenAI key from the keyring: {}",
+            e
+        )),
+    }
+}
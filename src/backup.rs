@@ -0,0 +1,104 @@
+use crate::{list, write_autocommits, CronLine};
+use color_eyre::{eyre::eyre, Result};
+use serde::{Deserialize, Serialize};
+use std::fs::canonicalize;
+use tracing::info;
+
+/// The configured autocommits, in a form that can be moved between machines.
+#[derive(Debug, Default, Deserialize, Serialize)]
+struct Backup {
+    #[serde(default, rename = "autocommit")]
+    autocommits: Vec<Entry>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct Entry {
+    path: String,
+    /// The five cron schedule fields.
+    schedule: String,
+    #[serde(default)]
+    paused: bool,
+    /// `run` options, e.g. `["--push", "commits:5"]`.
+    #[serde(default)]
+    options: Vec<String>,
+}
+
+/// Every configured autocommit as TOML.
+pub fn export() -> Result<String> {
+    let backup = Backup {
+        autocommits: list()?
+            .into_iter()
+            .map(|cron_line| Entry {
+                path: cron_line.repo_path().to_string(),
+                schedule: cron_line.frequency.join(" "),
+                paused: cron_line.paused,
+                options: cron_line.args.iter().skip(2).cloned().collect(),
+            })
+            .collect(),
+    };
+    Ok(toml::to_string(&backup)?)
+}
+
+/// Where a repo from the backup lives on this machine, asking for the new
+/// location if it isn't at the old path. `None` skips it.
+fn remap(path: &str) -> Result<Option<std::path::PathBuf>> {
+    let mut path = path.to_string();
+    loop {
+        if let Ok(found) = canonicalize(&path) {
+            if found.join(".git").is_dir() {
+                return Ok(Some(found));
+            }
+        }
+        path = dialoguer::Input::<String>::new()
+            .with_prompt(format!(
+                "{} is not a git repo here, new path (empty to skip)",
+                path
+            ))
+            .allow_empty(true)
+            .interact_text()?;
+        if path.trim().is_empty() {
+            return Ok(None);
+        }
+    }
+}
+
+/// Add the autocommits from an exported backup, running them with this
+/// machine's autocommit binary. Repos that are already configured are left
+/// as they are.
+pub fn import(contents: &str) -> Result<()> {
+    let backup: Backup =
+        toml::from_str(contents).map_err(|e| eyre!("Invalid autocommit export: {}", e))?;
+    let command_path = canonicalize(std::env::current_exe()?)?
+        .to_string_lossy()
+        .to_string();
+    let mut autocommits = list()?;
+    for entry in backup.autocommits {
+        let frequency = entry.schedule.split_whitespace().collect::<Vec<_>>();
+        let frequency: [String; 5] = match frequency.as_slice() {
+            [a, b, c, d, e] => [a, b, c, d, e].map(|f| f.to_string()),
+            _ => {
+                return Err(eyre!(
+                    "Invalid schedule {} for {}",
+                    entry.schedule,
+                    entry.path
+                ))
+            }
+        };
+        let Some(path) = remap(&entry.path)? else {
+            info!("Skipping {}", entry.path);
+            continue;
+        };
+        let path = path.to_string_lossy().to_string();
+        if autocommits.iter().any(|a| a.repo_path() == path) {
+            info!("Autocommit already exists on {}, skipping", path);
+            continue;
+        }
+        let mut args = vec!["run".to_string(), path.clone()];
+        args.extend(entry.options);
+        let mut cron_line = CronLine::new(frequency, command_path.clone(), args);
+        cron_line.paused = entry.paused;
+        autocommits.push(cron_line);
+        info!("Imported {}", path);
+    }
+    write_autocommits(&autocommits)
+}
@@ -0,0 +1,63 @@
+use crate::history::is_autocommit;
+use crate::squash::parse_since;
+use crate::{config, run_checked_command_in_dir, state, Generation, MessageGenerator};
+use color_eyre::{eyre::eyre, Result};
+use tracing::{info, info_span, Instrument};
+
+/// The subjects of the commits reachable from HEAD since `since`, a
+/// `today`/`yesterday`/`YYYY-MM-DD` date or a tag (any revision works), oldest
+/// first. Only autocommits unless `include_manual`.
+pub fn commits_since(
+    repo_path: &std::path::PathBuf,
+    since: &str,
+    include_manual: bool,
+) -> Result<Vec<String>> {
+    let state = state::State::load()?;
+    let repo_state = state.get(repo_path);
+    let commit_config = config::Config::load()?.commit;
+    // Records are separated by \x1e, the message follows \x1f.
+    let mut args = vec![
+        "log".to_string(),
+        "--no-merges".to_string(),
+        "--reverse".to_string(),
+        "--format=%H%x1f%B%x1e".to_string(),
+    ];
+    match parse_since(since) {
+        Ok(at) => args.push(format!("--since={}", at.to_rfc3339())),
+        Err(_) => args.push(format!("{}..HEAD", since)),
+    }
+    let args = args.iter().map(|a| a.as_str()).collect::<Vec<_>>();
+    let log = run_checked_command_in_dir(repo_path, "git", &args)?;
+    Ok(log
+        .split('\x1e')
+        .filter_map(|record| record.trim_start().split_once('\x1f'))
+        .filter(|(sha, message)| {
+            include_manual || is_autocommit(repo_state, &commit_config, sha, message)
+        })
+        .filter_map(|(_, message)| message.lines().next())
+        .map(|subject| subject.trim().to_string())
+        .filter(|subject| !subject.is_empty())
+        .collect())
+}
+
+/// A Markdown changelog of the commits since `since`, grouped by the
+/// generator.
+pub async fn changelog(
+    repo_path: &std::path::PathBuf,
+    generator: &dyn MessageGenerator,
+    since: &str,
+    include_manual: bool,
+) -> Result<(String, Generation)> {
+    if !repo_path.join(".git").is_dir() {
+        return Err(eyre!("Path is not a git repo"));
+    }
+    let commits = commits_since(repo_path, since, include_manual)?;
+    if commits.is_empty() {
+        return Err(eyre!("No commits since {}", since));
+    }
+    info!("Writing a changelog for {} commits", commits.len());
+    generator
+        .changelog(&commits)
+        .instrument(info_span!("changelog", phase = "changelog"))
+        .await
+}
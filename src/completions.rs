@@ -0,0 +1,46 @@
+use clap_complete::Shell;
+
+/// Shell code appended to the generated completions, so `run`, `delete` and
+/// `status` complete the configured repo paths (from the hidden
+/// `complete-repos` command) as they are at completion time. Other shells only
+/// get the static completions.
+pub fn repo_completion(shell: Shell) -> &'static str {
+    match shell {
+        Shell::Bash => {
+            r#"
+_autocommit_repos() {
+    local cur="${COMP_WORDS[COMP_CWORD]}"
+    case "${COMP_WORDS[1]}" in
+        run|delete|status)
+            if [[ "$cur" != -* ]]; then
+                COMPREPLY=($(compgen -W "$(autocommit complete-repos 2>/dev/null)" -- "$cur"))
+                [[ ${#COMPREPLY[@]} -gt 0 ]] && return 0
+            fi
+            ;;
+    esac
+    _autocommit "$@"
+}
+complete -F _autocommit_repos -o bashdefault -o default autocommit
+"#
+        }
+        Shell::Zsh => {
+            r#"
+_autocommit_repos() {
+    if [[ ${words[2]} == (run|delete|status) && ${words[CURRENT]} != -* ]]; then
+        local -a repos
+        repos=(${(f)"$(autocommit complete-repos 2>/dev/null)"})
+        compadd -a repos && return 0
+    fi
+    _autocommit "$@"
+}
+compdef _autocommit_repos autocommit
+"#
+        }
+        Shell::Fish => {
+            r#"
+complete -c autocommit -n "__fish_seen_subcommand_from run delete status" -f -a "(autocommit complete-repos 2>/dev/null)"
+"#
+        }
+        _ => "",
+    }
+}
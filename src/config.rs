@@ -0,0 +1,355 @@
+use color_eyre::{eyre::eyre, Result};
+use serde::{Deserialize, Serialize};
+
+/// Global settings, read from `~/.config/autocommit/config.toml`.
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Config {
+    pub email: Option<EmailConfig>,
+    pub metrics: Option<MetricsConfig>,
+    pub tracing: Option<TracingConfig>,
+    pub sentry: Option<SentryConfig>,
+    pub logging: LoggingConfig,
+    pub commit: CommitConfig,
+    pub branches: BranchesConfig,
+    pub generator: GeneratorConfig,
+    pub timeouts: TimeoutsConfig,
+    /// Settings for individual repos, keyed by their path.
+    pub repos: std::collections::BTreeMap<String, RepoSettings>,
+    /// Environment variables stored encrypted with the key file, see
+    /// `autocommit secret encrypt`.
+    pub secrets: std::collections::BTreeMap<String, String>,
+}
+
+/// SMTP settings for failure alerts.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct EmailConfig {
+    pub smtp_host: String,
+    #[serde(default = "default_smtp_port")]
+    pub smtp_port: u16,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub from: String,
+    pub to: String,
+    /// Send an alert once a repo has failed this many runs in a row.
+    #[serde(default = "default_after_failures")]
+    pub after_failures: u32,
+}
+
+/// Where run metrics are exported to.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct MetricsConfig {
+    /// Prometheus Pushgateway base URL, e.g. `http://localhost:9091`.
+    pub pushgateway: String,
+}
+
+/// Where run traces are exported to.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct TracingConfig {
+    /// OTLP gRPC collector endpoint, e.g. `http://localhost:4317`.
+    /// `OTEL_EXPORTER_OTLP_ENDPOINT` overrides it.
+    pub otlp_endpoint: Option<String>,
+}
+
+/// Where panics and failed runs are reported to.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct SentryConfig {
+    /// Sentry project DSN. `SENTRY_DSN` overrides it.
+    pub dsn: Option<String>,
+    /// Sentry environment events are tagged with, e.g. `work-laptop`.
+    pub environment: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct LoggingConfig {
+    /// `AUTOCOMMIT_LOG_FORMAT` overrides it.
+    pub format: LogFormat,
+    /// Per-repo logs are rotated once they reach this size.
+    pub max_size_bytes: u64,
+    /// How many rotated logs to keep per repo.
+    pub max_files: u32,
+    /// Rotated logs older than this are deleted.
+    pub max_age_days: u64,
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        Self {
+            format: LogFormat::default(),
+            max_size_bytes: 1024 * 1024,
+            max_files: 5,
+            max_age_days: 30,
+        }
+    }
+}
+
+/// How autocommit marks the commits it makes.
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct CommitConfig {
+    /// Trailer appended to every autocommit message, empty to disable.
+    pub trailer: String,
+    /// Also append an `Autocommit-Version:` trailer.
+    pub version_trailer: bool,
+    /// Append `Signed-off-by:` with the repo's committer identity, for
+    /// projects that require a DCO sign-off.
+    pub signoff: bool,
+    /// Append `Co-authored-by:` crediting this identity, e.g.
+    /// `CommitBot <commitbot@example.com>`.
+    pub co_author: Option<String>,
+}
+
+impl Default for CommitConfig {
+    fn default() -> Self {
+        Self {
+            trailer: "Autocommit: true".to_string(),
+            version_trailer: false,
+            signoff: false,
+            co_author: None,
+        }
+    }
+}
+
+/// How commit messages are generated.
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct GeneratorConfig {
+    /// Shell command that is given the diff on stdin and prints the commit
+    /// message, used instead of OpenAI when set.
+    pub command: Option<String>,
+    /// OpenAI requests allowed per minute across every autocommit process,
+    /// 0 for no limit.
+    pub requests_per_minute: u32,
+    /// Message used when no model is available, see
+    /// [`crate::fallback_message`] for the placeholders.
+    pub fallback_template: Option<String>,
+    /// Never call a model, always commit with the fallback message.
+    pub disabled: bool,
+}
+
+/// How long runs may take, in seconds, 0 for no limit.
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct TimeoutsConfig {
+    /// The whole run; it is aborted once this passes, killing any command
+    /// (hooks, `pre_commit`, pushes) still running.
+    pub run_secs: u64,
+    /// Generating the commit message.
+    pub generate_secs: u64,
+    /// Each `git push`, of the branch or a tag, which is killed when it
+    /// takes longer.
+    pub push_secs: u64,
+}
+
+impl Default for TimeoutsConfig {
+    fn default() -> Self {
+        Self {
+            run_secs: 15 * 60,
+            generate_secs: 2 * 60,
+            push_secs: 5 * 60,
+        }
+    }
+}
+
+impl TimeoutsConfig {
+    pub fn run(&self) -> Option<std::time::Duration> {
+        limit(self.run_secs)
+    }
+
+    pub fn generate(&self) -> Option<std::time::Duration> {
+        limit(self.generate_secs)
+    }
+
+    pub fn push(&self) -> Option<std::time::Duration> {
+        limit(self.push_secs)
+    }
+}
+
+fn limit(secs: u64) -> Option<std::time::Duration> {
+    (secs > 0).then(|| std::time::Duration::from_secs(secs))
+}
+
+/// Settings for a single repo, under `[repos."/path/to/repo"]`.
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct RepoSettings {
+    /// Shell commands run in the repo before committing, e.g. `cargo fmt`.
+    /// Changes they make are included in the commit.
+    pub pre_commit: Vec<String>,
+    /// What to do when a pre-commit command fails.
+    pub on_pre_commit_failure: PreCommitFailure,
+    /// Shell commands run after each autocommit, with `AUTOCOMMIT_REPO`,
+    /// `AUTOCOMMIT_SHA`, `AUTOCOMMIT_BRANCH` and `AUTOCOMMIT_MESSAGE` set.
+    pub post_commit: Vec<String>,
+    /// Like `post_commit`, but only run once the commit was pushed.
+    pub post_push: Vec<String>,
+    /// What the repo holds, which tunes how messages are written.
+    pub profile: Profile,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Profile {
+    /// Source code, described by what the changes do.
+    #[default]
+    Code,
+    /// A Markdown notes vault (e.g. Obsidian), described by the notes and
+    /// headings that changed.
+    Notes,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PreCommitFailure {
+    /// Fail the run without committing.
+    #[default]
+    Abort,
+    /// Log the failure and commit anyway.
+    Commit,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct BranchesConfig {
+    /// Branches autocommit refuses to commit to, `*` matches any characters.
+    pub protected: Vec<String>,
+    /// Commit to protected branches anyway.
+    pub allow_protected: bool,
+}
+
+impl Default for BranchesConfig {
+    fn default() -> Self {
+        Self {
+            protected: vec![
+                "main".to_string(),
+                "master".to_string(),
+                "release/*".to_string(),
+            ],
+            allow_protected: false,
+        }
+    }
+}
+
+impl BranchesConfig {
+    /// The protected pattern `branch` matches, if any.
+    pub fn protecting(&self, branch: &str) -> Option<&str> {
+        self.protected
+            .iter()
+            .find(|pattern| glob_match(pattern, branch))
+            .map(|pattern| pattern.as_str())
+    }
+}
+
+/// Match `text` against `pattern`, where `*` matches any run of characters.
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    match pattern.split_once('*') {
+        None => pattern == text,
+        Some((prefix, rest)) => {
+            let Some(text) = text.strip_prefix(prefix) else {
+                return false;
+            };
+            (0..=text.len())
+                .filter(|&i| text.is_char_boundary(i))
+                .any(|i| glob_match(rest, &text[i..]))
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// Human readable lines.
+    #[default]
+    Text,
+    /// One JSON object per line, with the run's span fields (repo, phase).
+    Json,
+}
+
+impl std::str::FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(format!("Invalid log format {}, expected text or json", s)),
+        }
+    }
+}
+
+fn default_smtp_port() -> u16 {
+    587
+}
+
+fn default_after_failures() -> u32 {
+    3
+}
+
+/// `$XDG_CONFIG_HOME/autocommit`, defaulting to `~/.config/autocommit`.
+pub fn config_dir() -> Result<std::path::PathBuf> {
+    xdg_dir("XDG_CONFIG_HOME", ".config")
+}
+
+/// `$XDG_STATE_HOME/autocommit`, defaulting to `~/.local/state/autocommit`.
+pub fn state_dir() -> Result<std::path::PathBuf> {
+    xdg_dir("XDG_STATE_HOME", ".local/state")
+}
+
+fn xdg_dir(var: &str, default: &str) -> Result<std::path::PathBuf> {
+    let base = match std::env::var_os(var) {
+        Some(dir) if !dir.is_empty() => std::path::PathBuf::from(dir),
+        _ => dirs::home_dir()
+            .ok_or_else(|| eyre!("Could not find the home directory"))?
+            .join(default),
+    };
+    Ok(base.join(crate::COMMAND_NAME))
+}
+
+impl Config {
+    pub fn path() -> Result<std::path::PathBuf> {
+        Ok(config_dir()?.join("config.toml"))
+    }
+
+    /// Load the config file, or the defaults if there is none.
+    pub fn load() -> Result<Self> {
+        let path = Self::path()?;
+        if !path.exists() {
+            return Ok(Config::default());
+        }
+        let contents = std::fs::read_to_string(&path)?;
+        toml::from_str(&contents).map_err(|e| eyre!("Invalid config {}: {}", path.display(), e))
+    }
+
+    /// The settings for the repo at `repo_path`, the defaults if it has none.
+    pub fn repo(&self, repo_path: &std::path::Path) -> RepoSettings {
+        self.repos
+            .get(repo_path.to_string_lossy().as_ref())
+            .cloned()
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn glob_match_without_wildcard_is_exact() {
+        assert!(glob_match("main", "main"));
+        assert!(!glob_match("main", "main2"));
+        assert!(!glob_match("main", "mai"));
+    }
+
+    #[test]
+    fn glob_match_wildcards() {
+        assert!(glob_match("*", ""));
+        assert!(glob_match("release/*", "release/1.0"));
+        assert!(glob_match("release/*", "release/"));
+        assert!(!glob_match("release/*", "releases/1.0"));
+        assert!(glob_match("*.swp", ".main.rs.swp"));
+        assert!(glob_match("a*b*c", "aXbYbZc"));
+        assert!(!glob_match("a*b*c", "aXbYbZ"));
+        assert!(glob_match("é*ü", "éaöü"));
+    }
+}
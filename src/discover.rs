@@ -0,0 +1,127 @@
+use crate::config::glob_match;
+use crate::{list, new_cron_line, state, write_autocommits, Frequency};
+use clap::Args;
+use color_eyre::Result;
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+/// Re-scan synced directories at most this often.
+const SYNC_INTERVAL_SECS: i64 = 60 * 60;
+
+#[derive(Args, Debug, Default, Clone)]
+pub struct DiscoverArgs {
+    /// Register every git repo found under this directory instead of a single path.
+    #[clap(long, conflicts_with = "path")]
+    pub discover: Option<std::path::PathBuf>,
+
+    /// How many directories deep to look for repos.
+    #[clap(long, default_value_t = 3, requires = "discover")]
+    pub depth: usize,
+
+    /// Skip directories whose name matches this pattern (`*` wildcards), can
+    /// be repeated.
+    #[clap(long, requires = "discover")]
+    pub ignore: Vec<String>,
+
+    /// Keep registering repos cloned under the directory later on.
+    #[clap(long, requires = "discover")]
+    pub sync: bool,
+}
+
+/// A directory `--discover --sync` keeps watching for new repos.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct DiscoverRoot {
+    pub dir: String,
+    pub frequency: Frequency,
+    pub depth: usize,
+    pub ignore: Vec<String>,
+    /// `run` options for discovered repos.
+    pub options: Vec<String>,
+}
+
+/// Git repos under `dir`, not looking inside repos or hidden directories.
+pub fn find_repos(
+    dir: &std::path::Path,
+    depth: usize,
+    ignore: &[String],
+) -> Result<Vec<std::path::PathBuf>> {
+    let mut repos = Vec::new();
+    if dir.join(".git").exists() {
+        repos.push(dir.to_path_buf());
+        return Ok(repos);
+    }
+    if depth == 0 {
+        return Ok(repos);
+    }
+    let mut entries = std::fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().map_or(false, |t| t.is_dir()))
+        .map(|entry| entry.path())
+        .collect::<Vec<_>>();
+    entries.sort();
+    for path in entries {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        if name.starts_with('.') || ignore.iter().any(|pattern| glob_match(pattern, &name)) {
+            continue;
+        }
+        // Unreadable directories are skipped rather than failing the scan.
+        repos.extend(find_repos(&path, depth - 1, ignore).unwrap_or_default());
+    }
+    Ok(repos)
+}
+
+/// Register an autocommit for every repo under `root` that doesn't have one
+/// yet, returning how many were added.
+pub fn register(root: &DiscoverRoot) -> Result<usize> {
+    let repos = find_repos(std::path::Path::new(&root.dir), root.depth, &root.ignore)?;
+    let mut autocommits = list()?;
+    let mut added = 0;
+    for repo in repos {
+        let path = repo.to_string_lossy();
+        if autocommits.iter().any(|a| a.repo_path() == path) {
+            continue;
+        }
+        info!("Creating autocommit on {}", path);
+        autocommits.push(new_cron_line(&repo, root.frequency, root.options.clone())?);
+        added += 1;
+    }
+    if added > 0 {
+        write_autocommits(&autocommits)?;
+    }
+    Ok(added)
+}
+
+/// Remember `root` so later syncs pick up repos cloned into it.
+pub fn watch(root: DiscoverRoot) -> Result<()> {
+    state::State::update(|state| {
+        state.discover.retain(|existing| existing.dir != root.dir);
+        state.discover.push(root);
+        Ok(())
+    })
+}
+
+/// Register new repos under every watched directory.
+pub fn sync() -> Result<usize> {
+    let roots = state::State::update(|state| {
+        state.last_discover_at = Some(chrono::Utc::now().timestamp());
+        Ok(state.discover.clone())
+    })?;
+    let mut added = 0;
+    for root in &roots {
+        added += register(root)?;
+    }
+    Ok(added)
+}
+
+/// `sync` if watched directories haven't been scanned in a while, so runs
+/// pick up newly cloned repos without a separate schedule.
+pub fn sync_if_due() -> Result<()> {
+    let state = state::State::load()?;
+    let due = state.last_discover_at.map_or(true, |at| {
+        chrono::Utc::now().timestamp() - at >= SYNC_INTERVAL_SECS
+    });
+    if !state.discover.is_empty() && due {
+        sync()?;
+    }
+    Ok(())
+}
@@ -0,0 +1,211 @@
+use crate::{auth, config, list, logs, push_refspec, run_checked_command_in_dir, CronLine};
+use color_eyre::{eyre::eyre, Result};
+use std::process::Command;
+use std::time::Duration;
+
+const API_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A failed check and what to do about it.
+struct Problem {
+    error: String,
+    fix: String,
+}
+
+impl Problem {
+    fn new(error: impl ToString, fix: impl ToString) -> Self {
+        Self {
+            error: error.to_string(),
+            fix: fix.to_string(),
+        }
+    }
+}
+
+type Check = std::result::Result<String, Problem>;
+
+/// Print a check's result, returning whether it passed.
+fn report(name: &str, check: Check) -> bool {
+    let passed = check.is_ok();
+    match check {
+        Ok(detail) if detail.is_empty() => println!("  ok    {}", name),
+        Ok(detail) => println!("  ok    {} ({})", name, detail),
+        Err(problem) => {
+            println!("  FAIL  {}: {}", name, problem.error);
+            println!("        fix: {}", problem.fix);
+        }
+    }
+    passed
+}
+
+fn check_config() -> Check {
+    config::Config::load()
+        .map(|_| String::new())
+        .map_err(|e| Problem::new(e, "fix or remove the config file"))
+}
+
+fn check_api_key() -> Check {
+    let key = auth::openai_api_key()
+        .map_err(|e| Problem::new(e, "run `autocommit auth set` or set OPENAI_API_KEY"))?;
+    if !key.starts_with("sk-") {
+        return Err(Problem::new(
+            "the key doesn't look like an OpenAI key",
+            "check the key with `autocommit auth set`",
+        ));
+    }
+    // Listing models is free and fails fast on a bad key.
+    match ureq::get("https://api.openai.com/v1/models")
+        .set("Authorization", &format!("Bearer {}", key))
+        .timeout(API_TIMEOUT)
+        .call()
+    {
+        Ok(_) => Ok(String::new()),
+        Err(ureq::Error::Status(401, _)) => Err(Problem::new(
+            "OpenAI rejected the key",
+            "create a new key and store it with `autocommit auth set`",
+        )),
+        Err(e) => Err(Problem::new(
+            e,
+            "check your network connection and https://status.openai.com",
+        )),
+    }
+}
+
+fn check_repo(repo_path: &std::path::PathBuf) -> Check {
+    run_checked_command_in_dir(repo_path, "git", &["rev-parse", "--git-dir"]).map_err(|e| {
+        Problem::new(
+            e,
+            "fix the path with `autocommit delete` and `autocommit create`",
+        )
+    })?;
+    Ok(String::new())
+}
+
+fn check_head(repo_path: &std::path::PathBuf) -> Check {
+    run_checked_command_in_dir(repo_path, "git", &["rev-parse", "--verify", "HEAD"])
+        .map_err(|_| Problem::new("HEAD has no commits", "make an initial commit"))?;
+    let branch = run_checked_command_in_dir(repo_path, "git", &["symbolic-ref", "--short", "HEAD"])
+        .map_err(|_| Problem::new("HEAD is detached", "check out a branch"))?;
+    Ok(branch.trim().to_string())
+}
+
+/// Dry-run a push with prompts disabled, which checks both that the remote is
+/// reachable and that the credentials cron will use work.
+fn check_remote(repo_path: &std::path::PathBuf) -> Check {
+    // Check the same destination runs push to, see `push_refspec`.
+    let mut args = vec!["push".to_string(), "--dry-run".into(), "--porcelain".into()];
+    if let Some(refspec) = push_refspec(repo_path) {
+        args.extend([refspec.remote, format!("{}:{}", refspec.src, refspec.dst)]);
+    }
+    let output = Command::new("git")
+        .args(&args)
+        .env("GIT_TERMINAL_PROMPT", "0")
+        .env("GIT_SSH_COMMAND", "ssh -o BatchMode=yes")
+        .current_dir(repo_path)
+        .output()
+        .map_err(|e| Problem::new(e, "install git"))?;
+    if output.status.success() {
+        return Ok(String::new());
+    }
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let fix = if stderr.contains("no upstream") || stderr.contains("No configured push destination")
+    {
+        "set an upstream with `git push -u origin <branch>`"
+    } else if stderr.contains("Permission denied")
+        || stderr.contains("Authentication failed")
+        || stderr.contains("could not read Username")
+    {
+        "set up an SSH key agent or credential helper that works without a terminal"
+    } else {
+        "check the remote URL with `git remote -v` and your network connection"
+    };
+    Err(Problem::new(
+        stderr.lines().last().unwrap_or("push failed").trim(),
+        fix,
+    ))
+}
+
+fn check_schedule(repo_path: &std::path::Path, autocommits: &[CronLine]) -> Check {
+    match autocommits
+        .iter()
+        .find(|a| std::path::Path::new(a.repo_path()) == repo_path)
+    {
+        Some(a) if a.paused => Err(Problem::new(
+            "the schedule is paused",
+            "resume it from `autocommit ui`",
+        )),
+        Some(a) => Ok(a.frequency.join(" ")),
+        None => Err(Problem::new(
+            "no crontab entry",
+            "run `autocommit create --path <repo> --frequency <minutes>`",
+        )),
+    }
+}
+
+fn check_log(repo_path: &std::path::Path) -> Check {
+    let path = logs::log_path(repo_path).map_err(|e| Problem::new(e, "set HOME"))?;
+    let writable = path
+        .parent()
+        .map(std::fs::create_dir_all)
+        .transpose()
+        .and_then(|_| {
+            std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&path)
+        });
+    match writable {
+        Ok(_) => Ok(path.display().to_string()),
+        Err(e) => Err(Problem::new(
+            format!("{}: {}", path.display(), e),
+            "fix the permissions of the log directory",
+        )),
+    }
+}
+
+/// Check everything runs need, for `repo_path` or every configured repo.
+pub fn doctor(repo_path: Option<std::path::PathBuf>) -> Result<()> {
+    let mut failures = 0;
+    println!("General");
+    let crontab =
+        list().map_err(|e| Problem::new(e, "install cron and make sure `crontab -l` works"));
+    let autocommits = crontab.as_ref().cloned().unwrap_or_default();
+    let checks = [
+        (
+            "crontab readable",
+            crontab.map(|a| format!("{} autocommits", a.len())),
+        ),
+        ("config file", check_config()),
+        ("OpenAI key", check_api_key()),
+    ];
+    for (name, check) in checks {
+        failures += usize::from(!report(name, check));
+    }
+
+    let repos = match repo_path {
+        Some(path) => vec![path],
+        None => autocommits
+            .iter()
+            .map(|a| std::path::PathBuf::from(a.repo_path()))
+            .collect(),
+    };
+    for repo in repos {
+        println!("{}", repo.display());
+        if !report("repo opens", check_repo(&repo)) {
+            failures += 1;
+            continue;
+        }
+        let checks = [
+            ("branch", check_head(&repo)),
+            ("remote and credentials", check_remote(&repo)),
+            ("schedule", check_schedule(&repo, &autocommits)),
+            ("log writable", check_log(&repo)),
+        ];
+        for (name, check) in checks {
+            failures += usize::from(!report(name, check));
+        }
+    }
+
+    if failures > 0 {
+        return Err(eyre!("{} checks failed", failures));
+    }
+    Ok(())
+}
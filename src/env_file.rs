@@ -0,0 +1,76 @@
+use crate::config::config_dir;
+use color_eyre::{eyre::eyre, Result};
+use std::collections::BTreeMap;
+use std::io::Write;
+
+/// Repo-local env file, kept out of git through `.git/info/exclude`.
+pub const REPO_ENV_FILE: &str = ".autocommit.env";
+
+/// Parse `KEY=VALUE` lines, allowing comments, blank lines, an `export `
+/// prefix and quoted values.
+fn parse(path: &std::path::Path) -> Result<BTreeMap<String, String>> {
+    let contents = std::fs::read_to_string(path)?;
+    let mut vars = BTreeMap::new();
+    for (number, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line = line.strip_prefix("export ").unwrap_or(line);
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| eyre!("{}:{}: expected KEY=VALUE", path.display(), number + 1))?;
+        let value = value.trim();
+        let value = value
+            .strip_prefix('"')
+            .and_then(|v| v.strip_suffix('"'))
+            .or_else(|| value.strip_prefix('\'').and_then(|v| v.strip_suffix('\'')))
+            .unwrap_or(value);
+        vars.insert(key.trim().to_string(), value.to_string());
+    }
+    Ok(vars)
+}
+
+/// Make sure the repo env file never ends up in a commit.
+fn exclude(repo_path: &std::path::PathBuf) -> Result<()> {
+    let exclude = repo_path.join(".git").join("info").join("exclude");
+    let contents = std::fs::read_to_string(&exclude).unwrap_or_default();
+    let pattern = format!("/{}", REPO_ENV_FILE);
+    if contents.lines().any(|line| line.trim() == pattern) {
+        return Ok(());
+    }
+    std::fs::create_dir_all(exclude.parent().unwrap())?;
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&exclude)?;
+    if !contents.is_empty() && !contents.ends_with('\n') {
+        writeln!(file)?;
+    }
+    writeln!(file, "{}", pattern)?;
+    Ok(())
+}
+
+/// Load `~/.config/autocommit/env` and then, for commands on a repo, the
+/// repo's `.autocommit.env`, so the repo's values win. Variables already set
+/// in the environment are left alone.
+pub fn load(repo_path: Option<&std::path::PathBuf>) -> Result<()> {
+    let mut vars = BTreeMap::new();
+    let global = config_dir()?.join("env");
+    if global.exists() {
+        vars.extend(parse(&global)?);
+    }
+    if let Some(repo_path) = repo_path {
+        let repo = repo_path.join(REPO_ENV_FILE);
+        if repo.exists() {
+            exclude(repo_path)?;
+            vars.extend(parse(&repo)?);
+        }
+    }
+    for (key, value) in vars {
+        if std::env::var_os(&key).is_none() {
+            std::env::set_var(key, value);
+        }
+    }
+    Ok(())
+}
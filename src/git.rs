@@ -0,0 +1,517 @@
+use crate::{junk, PushPolicy};
+use color_eyre::{eyre::eyre, Result};
+use std::collections::HashSet;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::process::{Command, Output, Stdio};
+use std::time::{Duration, Instant};
+use tracing::debug;
+
+tokio::task_local! {
+    /// When the current run has to be done by, see `with_deadline`.
+    static RUN_DEADLINE: Instant;
+}
+
+/// Run `future` with a deadline that the commands it starts through
+/// `output_with_timeout` honour: they are killed once it passes, so a hung
+/// hook or push can't keep the run going past its limit.
+pub async fn with_deadline<F: std::future::Future>(deadline: Instant, future: F) -> F::Output {
+    RUN_DEADLINE.scope(deadline, future).await
+}
+
+/// `timeout`, cut short to what is left of the current run's deadline.
+fn within_deadline(timeout: Option<Duration>) -> Option<Duration> {
+    let left = RUN_DEADLINE
+        .try_with(|deadline| deadline.saturating_duration_since(Instant::now()))
+        .ok();
+    match (timeout, left) {
+        (Some(timeout), Some(left)) => Some(timeout.min(left)),
+        (timeout, left) => timeout.or(left),
+    }
+}
+
+/// Like `Command::output`, but kills the command and fails if it is still
+/// running after `timeout`, or once the current run's deadline passes.
+pub fn output_with_timeout(command: &mut Command, timeout: Option<Duration>) -> Result<Output> {
+    let Some(timeout) = within_deadline(timeout) else {
+        return Ok(command.output()?);
+    };
+    let mut child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    // Drain the pipes from other threads, so the command never blocks on a
+    // full pipe while we wait for it.
+    let stdout = read_in_background(child.stdout.take().unwrap());
+    let stderr = read_in_background(child.stderr.take().unwrap());
+    let deadline = Instant::now() + timeout;
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if Instant::now() >= deadline {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(eyre!(
+                "`{}` did not finish within {}s",
+                describe(command),
+                timeout.as_secs()
+            ));
+        }
+        std::thread::sleep(Duration::from_millis(100));
+    };
+    Ok(Output {
+        status,
+        stdout: stdout.join().unwrap_or_default(),
+        stderr: stderr.join().unwrap_or_default(),
+    })
+}
+
+fn describe(command: &Command) -> String {
+    std::iter::once(command.get_program())
+        .chain(command.get_args())
+        .map(|part| part.to_string_lossy())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn read_in_background(mut pipe: impl Read + Send + 'static) -> std::thread::JoinHandle<Vec<u8>> {
+    std::thread::spawn(move || {
+        let mut output = Vec::new();
+        let _ = pipe.read_to_end(&mut output);
+        output
+    })
+}
+
+pub fn run_command_in_dir(
+    dir: &std::path::PathBuf,
+    command: &str,
+    args: &[&str],
+) -> Result<String> {
+    let output = output_with_timeout(
+        Command::new(command)
+            .args(args)
+            .current_dir(dir)
+            .stderr(Stdio::inherit()),
+        None,
+    )?;
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+/// Like `run_command_in_dir`, but fails if the command exits unsuccessfully.
+pub fn run_checked_command_in_dir(
+    dir: &std::path::PathBuf,
+    command: &str,
+    args: &[&str],
+) -> Result<String> {
+    run_checked_command_with_timeout(dir, command, args, None)
+}
+
+/// Like `run_checked_command_in_dir`, but kills the command and fails if it
+/// is still running after `timeout`.
+pub fn run_checked_command_with_timeout(
+    dir: &std::path::PathBuf,
+    command: &str,
+    args: &[&str],
+    timeout: Option<Duration>,
+) -> Result<String> {
+    let output = output_with_timeout(Command::new(command).args(args).current_dir(dir), timeout)?;
+    if !output.status.success() {
+        return Err(eyre!(
+            "`{} {}` failed: {}",
+            command,
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+/// Which changes `git diff` should report.
+#[derive(Debug, Clone, Copy)]
+pub enum DiffSource {
+    /// Working tree changes not yet in the index.
+    Unstaged,
+    /// Changes in the index relative to HEAD.
+    Staged,
+    /// Every change in the working tree relative to HEAD.
+    All,
+}
+
+pub fn git_diff(repo_path: &std::path::PathBuf, source: DiffSource) -> Result<String> {
+    let args: &[&str] = match source {
+        DiffSource::Unstaged => &["diff"],
+        DiffSource::Staged => &["diff", "--cached"],
+        DiffSource::All => &["diff", "HEAD"],
+    };
+    capped_diff(repo_path, args)
+}
+
+/// How much of a single file's diff is kept for message generation.
+pub const MAX_FILE_DIFF_BYTES: usize = 5000;
+/// How much of the whole diff is kept, about what message generation sends.
+pub const MAX_DIFF_BYTES: usize = 30000;
+
+/// `.gitattributes` attributes that keep a file's changes out of the diff
+/// given to the model. The file is still committed and listed by name.
+pub const IGNORE_DIFF_ATTRIBUTES: &[&str] = &["linguist-generated", "autocommit-ignore-diff"];
+
+/// Files in the diff `git <args>` prints that have one of the
+/// `IGNORE_DIFF_ATTRIBUTES` set.
+fn ignored_in_diff(repo_path: &std::path::PathBuf, args: &[&str]) -> Result<HashSet<String>> {
+    let mut name_args = vec![args[0], "--name-only", "-z"];
+    name_args.extend(&args[1..]);
+    let files = run_command_in_dir(repo_path, "git", &name_args)?;
+    if files.is_empty() {
+        return Ok(HashSet::new());
+    }
+    let mut check = Command::new("git")
+        .args(["check-attr", "--stdin", "-z"])
+        .args(IGNORE_DIFF_ATTRIBUTES)
+        .current_dir(repo_path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()?;
+    // Write from another thread, big change sets could fill the output pipe
+    // before all names are written.
+    let mut stdin = check.stdin.take().unwrap();
+    let writer = std::thread::spawn(move || stdin.write_all(files.as_bytes()));
+    let output = check.wait_with_output()?;
+    let _ = writer.join();
+    // `-z` prints `<path> NUL <attribute> NUL <value> NUL` for each pair.
+    let output = String::from_utf8_lossy(&output.stdout).to_string();
+    let fields = output.split('\0').collect::<Vec<_>>();
+    Ok(fields
+        .chunks(3)
+        .filter(|entry| entry.len() == 3 && (entry[2] == "set" || entry[2] == "true"))
+        .map(|entry| entry[0].to_string())
+        .collect())
+}
+
+/// Run `git <args>` and collect the patch it prints, keeping at most
+/// `MAX_FILE_DIFF_BYTES` of each file and stopping git once `MAX_DIFF_BYTES`
+/// were collected, so huge changes (generated files, vendored deps) are never
+/// held in memory. Files marked `linguist-generated` or
+/// `autocommit-ignore-diff` in `.gitattributes` are listed without their
+/// changes.
+pub fn capped_diff(repo_path: &std::path::PathBuf, args: &[&str]) -> Result<String> {
+    let ignored = ignored_in_diff(repo_path, args)?;
+    let mut child = Command::new("git")
+        .args(args)
+        .current_dir(repo_path)
+        .stdout(Stdio::piped())
+        .spawn()?;
+    let mut reader = BufReader::new(child.stdout.take().unwrap());
+    let mut diff = String::new();
+    let mut line = Vec::new();
+    let mut line_start = true;
+    let mut file_bytes = 0;
+    let mut file_truncated = false;
+    let mut file_ignored = false;
+    loop {
+        line.clear();
+        // Bound each read, so a single enormous line (minified files) is
+        // never buffered whole.
+        let read = (&mut reader)
+            .take(MAX_FILE_DIFF_BYTES as u64)
+            .read_until(b'\n', &mut line)?;
+        if read == 0 {
+            break;
+        }
+        if line_start && line.starts_with(b"diff --git ") {
+            file_bytes = 0;
+            file_truncated = false;
+            let header = String::from_utf8_lossy(&line);
+            let header = header.trim_end();
+            file_ignored = ignored
+                .iter()
+                .any(|file| header.ends_with(&format!(" b/{}", file)));
+            if file_ignored {
+                diff.push_str(header);
+                diff.push_str("\n[generated file, changes omitted]\n");
+            }
+        }
+        line_start = line.ends_with(b"\n");
+        if file_ignored {
+            continue;
+        }
+        if file_bytes + line.len() > MAX_FILE_DIFF_BYTES {
+            if !file_truncated {
+                diff.push_str("[... rest of this file's diff truncated]\n");
+                file_truncated = true;
+            }
+            continue;
+        }
+        file_bytes += line.len();
+        diff.push_str(&String::from_utf8_lossy(&line));
+        if diff.len() >= MAX_DIFF_BYTES {
+            debug!("Diff reached {} bytes, ignoring the rest", MAX_DIFF_BYTES);
+            break;
+        }
+    }
+    // Stop git if the budget ran out before the diff did.
+    let _ = child.kill();
+    let _ = child.wait();
+    Ok(diff)
+}
+
+/// Whether `rev` is already on the push target.
+pub fn is_pushed(repo_path: &std::path::PathBuf, rev: &str) -> Result<bool> {
+    Ok(match push_tracking_ref(repo_path) {
+        Some(tracking) => run_checked_command_in_dir(
+            repo_path,
+            "git",
+            &["merge-base", "--is-ancestor", rev, &tracking],
+        )
+        .is_ok(),
+        None => false,
+    })
+}
+
+/// Where `git push` sends the checked-out branch.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PushRefspec {
+    pub remote: String,
+    /// The local branch, e.g. `refs/heads/main`.
+    pub src: String,
+    /// The branch on the remote, e.g. `refs/heads/develop`.
+    pub dst: String,
+}
+
+impl PushRefspec {
+    /// The remote-tracking ref for `dst`, e.g. `refs/remotes/origin/develop`.
+    pub fn tracking_ref(&self) -> String {
+        format!("refs/remotes/{}/{}", self.remote, self.short_dst())
+    }
+
+    fn short_dst(&self) -> &str {
+        self.dst.trim_start_matches("refs/heads/")
+    }
+}
+
+impl std::fmt::Display for PushRefspec {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.remote, self.short_dst())
+    }
+}
+
+fn git_config(repo_path: &std::path::PathBuf, key: &str) -> Option<String> {
+    run_checked_command_in_dir(repo_path, "git", &["config", key])
+        .ok()
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty())
+}
+
+/// Resolve the push destination of the checked-out branch the way a plain
+/// `git push` does: the remote from `branch.<name>.pushRemote`,
+/// `remote.pushDefault` or `branch.<name>.remote`, and the remote branch from
+/// `branch.<name>.merge` when pushing back to the upstream's remote (unless
+/// `push.default` is `current`), else the branch's own name. `None` if HEAD
+/// is detached or none of these are configured, leaving it to `git push` to
+/// decide (and to refuse to publish a branch without an upstream).
+pub fn push_refspec(repo_path: &std::path::PathBuf) -> Option<PushRefspec> {
+    // HEAD can point at any branch layout, e.g. `refs/heads/team/me/wip`.
+    let src = run_checked_command_in_dir(repo_path, "git", &["symbolic-ref", "-q", "HEAD"]).ok()?;
+    let src = src.trim().to_string();
+    let name = src.strip_prefix("refs/heads/")?;
+    let upstream_remote = git_config(repo_path, &format!("branch.{}.remote", name));
+    let push_remote = git_config(repo_path, &format!("branch.{}.pushRemote", name))
+        .or_else(|| git_config(repo_path, "remote.pushDefault"));
+    let upstream = git_config(repo_path, &format!("branch.{}.merge", name));
+    let remote = match (push_remote, &upstream_remote, &upstream) {
+        (Some(remote), _, _) => remote,
+        (None, Some(remote), Some(_)) => remote.clone(),
+        _ => return None,
+    };
+    let merge = upstream
+        .filter(|_| upstream_remote.as_deref() == Some(remote.as_str()))
+        .filter(|_| git_config(repo_path, "push.default").as_deref() != Some("current"));
+    Some(PushRefspec {
+        remote,
+        dst: merge.unwrap_or_else(|| src.clone()),
+        src,
+    })
+}
+
+/// The remote-tracking ref of the push destination, if it was pushed before.
+fn push_tracking_ref(repo_path: &std::path::PathBuf) -> Option<String> {
+    let tracking = push_refspec(repo_path)?.tracking_ref();
+    run_checked_command_in_dir(
+        repo_path,
+        "git",
+        &["rev-parse", "--verify", "-q", &tracking],
+    )
+    .is_ok()
+    .then_some(tracking)
+}
+
+/// The upstream `git push` would push to, e.g. `origin/main`, if the branch
+/// was pushed before.
+pub fn push_target(repo_path: &std::path::PathBuf) -> Result<Option<String>> {
+    Ok(push_tracking_ref(repo_path)
+        .and(push_refspec(repo_path))
+        .map(|refspec| refspec.to_string()))
+}
+
+/// Push the checked-out branch to its [`push_refspec`], spelled out so it
+/// works whatever `push.default` says and when the remote branch has a
+/// different name. Without one (detached HEAD, no upstream) this is a plain
+/// `git push`.
+pub fn push_branch(
+    repo_path: &std::path::PathBuf,
+    force_with_lease: bool,
+    timeout: Option<Duration>,
+) -> Result<String> {
+    let refspec = push_refspec(repo_path).map(|r| (r.remote, format!("{}:{}", r.src, r.dst)));
+    let mut args = vec!["push"];
+    if force_with_lease {
+        args.push("--force-with-lease");
+    }
+    if let Some((remote, refspec)) = &refspec {
+        args.extend([remote.as_str(), refspec.as_str()]);
+    }
+    run_checked_command_with_timeout(repo_path, "git", &args, timeout)
+}
+
+/// Commits in `range` (e.g. `refs/remotes/origin/main..HEAD`, the commits on the current
+/// branch that have not reached the push target yet), newest first, as
+/// `(sha, commit unix timestamp)`.
+pub fn unpushed_commits(repo_path: &std::path::PathBuf, range: &str) -> Result<Vec<(String, i64)>> {
+    let log = run_command_in_dir(repo_path, "git", &["log", "--format=%H %ct", range])?;
+    Ok(log
+        .lines()
+        .filter_map(|line| {
+            let (sha, time) = line.split_once(' ')?;
+            Some((sha.to_string(), time.parse().ok()?))
+        })
+        .collect())
+}
+
+pub fn should_push(repo_path: &std::path::PathBuf, policy: PushPolicy) -> Result<bool> {
+    // Without a push target there is nothing to batch against, leave it to `git push`.
+    match push_tracking_ref(repo_path) {
+        Some(tracking) => push_threshold_reached(repo_path, policy, &format!("{}..HEAD", tracking)),
+        None => Ok(policy != PushPolicy::Never),
+    }
+}
+
+/// Whether the unpushed commits in `range` are enough to push under `policy`.
+pub fn push_threshold_reached(
+    repo_path: &std::path::PathBuf,
+    policy: PushPolicy,
+    range: &str,
+) -> Result<bool> {
+    let threshold_reached = match policy {
+        PushPolicy::Always => true,
+        PushPolicy::Never => false,
+        PushPolicy::Commits(count) => unpushed_commits(repo_path, range)?.len() >= count as usize,
+        PushPolicy::Minutes(minutes) => match unpushed_commits(repo_path, range)?.last() {
+            Some((_, oldest)) => chrono::Utc::now().timestamp() - oldest >= i64::from(minutes) * 60,
+            None => false,
+        },
+    };
+    Ok(threshold_reached)
+}
+
+/// Whether any tracked file matching `pathspecs` (everything if empty)
+/// changed, ignoring junk files. Untracked files don't count, since `commit -a`
+/// never adds them and a run with only those would have nothing to commit.
+/// Skips refreshing the index on disk, so idle runs stay cheap and never
+/// contend with the user's own git commands.
+pub fn has_changes(repo_path: &std::path::PathBuf, pathspecs: &[String]) -> Result<bool> {
+    let junk = junk::exclude_pathspecs();
+    let mut args = vec![
+        "--no-optional-locks",
+        "status",
+        "--porcelain",
+        "--untracked-files=no",
+        "--",
+    ];
+    args.extend(pathspecs.iter().chain(&junk).map(|p| p.as_str()));
+    let status = run_checked_command_in_dir(repo_path, "git", &args)?;
+    Ok(!status.trim().is_empty())
+}
+
+/// Whether the index has changes relative to HEAD.
+pub fn has_staged_changes(repo_path: &std::path::PathBuf) -> Result<bool> {
+    // `--quiet` exits with 1 if there are differences.
+    let status = Command::new("git")
+        .args(["diff", "--cached", "--quiet"])
+        .current_dir(repo_path)
+        .status()?;
+    match status.code() {
+        Some(0) => Ok(false),
+        Some(1) => Ok(true),
+        _ => Err(eyre!("`git diff --cached --quiet` failed with {}", status)),
+    }
+}
+
+/// Tracked files with changes relative to HEAD, i.e. what `commit -a` would pick up.
+pub fn changed_files(repo_path: &std::path::PathBuf) -> Result<Vec<String>> {
+    let files = run_command_in_dir(repo_path, "git", &["diff", "--name-only", "HEAD"])?;
+    Ok(files.lines().map(|f| f.to_string()).collect())
+}
+
+/// How long the oldest of `files` has been changed, from its modification
+/// time, but no longer than since `base` was committed. `None` if none of
+/// the files exist any more, e.g. they were all deleted.
+pub fn dirty_for(
+    repo_path: &std::path::PathBuf,
+    files: &[String],
+    base: &str,
+) -> Option<std::time::Duration> {
+    let oldest = files
+        .iter()
+        .filter_map(|f| std::fs::metadata(repo_path.join(f)).ok()?.modified().ok())
+        .min()?;
+    let base_time = run_command_in_dir(repo_path, "git", &["log", "-1", "--format=%ct", base])
+        .ok()
+        .and_then(|t| t.trim().parse::<u64>().ok())
+        .map(|t| std::time::UNIX_EPOCH + std::time::Duration::from_secs(t));
+    let since = base_time.map_or(oldest, |base_time| oldest.max(base_time));
+    std::time::SystemTime::now().duration_since(since).ok()
+}
+
+pub fn push(repo_path: &std::path::PathBuf) -> Result<()> {
+    if !repo_path.join(".git").is_dir() {
+        return Err(eyre!("Path is not a git repo"));
+    }
+    push_branch(repo_path, false, None)?;
+    Ok(())
+}
+
+/// Run git with a private index, so the real index is never touched.
+fn git_with_index(
+    repo_path: &std::path::PathBuf,
+    index: &std::path::Path,
+    args: &[&str],
+) -> Result<String> {
+    let output = Command::new("git")
+        .args(args)
+        .env("GIT_INDEX_FILE", index)
+        .current_dir(repo_path)
+        .output()?;
+    if !output.status.success() {
+        return Err(eyre!(
+            "`git {}` failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Write the working tree, untracked files included, as a tree object. Only
+/// changes matching `pathspecs` (all of them if empty) are picked up, and
+/// junk files are left out.
+pub fn working_tree(repo_path: &std::path::PathBuf, pathspecs: &[String]) -> Result<String> {
+    let index = repo_path.join(".git").join("autocommit-index");
+    let junk = junk::exclude_pathspecs();
+    let mut add = vec!["add", "-A", "--"];
+    add.extend(pathspecs.iter().chain(&junk).map(|p| p.as_str()));
+    let tree = git_with_index(repo_path, &index, &["read-tree", "HEAD"])
+        .and_then(|_| git_with_index(repo_path, &index, &add))
+        .and_then(|_| git_with_index(repo_path, &index, &["write-tree"]));
+    let _ = std::fs::remove_file(&index);
+    tree
+}
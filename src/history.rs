@@ -0,0 +1,109 @@
+use crate::config::{self, CommitConfig};
+use crate::{notes, run_checked_command_in_dir, state, trailers};
+use chrono::{Local, TimeZone};
+use color_eyre::Result;
+use serde::Serialize;
+
+/// A commit made by autocommit.
+#[derive(Serialize)]
+pub struct HistoryEntry {
+    pub sha: String,
+    /// Unix timestamp the commit was authored at.
+    pub authored_at: i64,
+    pub message: String,
+    pub files: Vec<String>,
+    /// How the message was generated, from the commit's git note.
+    pub generation: Option<notes::Note>,
+}
+
+impl std::fmt::Display for HistoryEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let at = Local
+            .timestamp_opt(self.authored_at, 0)
+            .single()
+            .map(|at| at.format("%Y-%m-%d %H:%M").to_string())
+            .unwrap_or_default();
+        write!(
+            f,
+            "{} {} {}",
+            &self.sha[..self.sha.len().min(10)],
+            at,
+            self.message.lines().next().unwrap_or_default()
+        )?;
+        if let Some(generation) = &self.generation {
+            write!(
+                f,
+                " ({}, {} tokens{})",
+                generation.model.as_deref().unwrap_or("unknown model"),
+                generation.prompt_tokens + generation.completion_tokens,
+                if generation.fallback {
+                    ", fallback"
+                } else {
+                    ""
+                }
+            )?;
+        }
+        for file in &self.files {
+            write!(f, "\n    {}", file)?;
+        }
+        Ok(())
+    }
+}
+
+/// Whether `sha` is a commit autocommit made, either recorded in this
+/// machine's state or marked with the autocommit trailer.
+pub fn is_autocommit(
+    repo_state: Option<&state::RepoState>,
+    commit_config: &CommitConfig,
+    sha: &str,
+    message: &str,
+) -> bool {
+    repo_state.is_some_and(|repo_state| repo_state.autocommits.iter().any(|a| a == sha))
+        || trailers::is_marked(message, commit_config)
+}
+
+/// The autocommits among the last `limit` commits reachable from HEAD, newest
+/// first.
+pub fn history(repo_path: &std::path::PathBuf, limit: usize) -> Result<Vec<HistoryEntry>> {
+    let state = state::State::load()?;
+    let repo_state = state.get(repo_path);
+    let commit_config = config::Config::load()?.commit;
+    // Records are separated by \x1e and fields by \x1f, the file names
+    // follow the last field.
+    let log = run_checked_command_in_dir(
+        repo_path,
+        "git",
+        &[
+            "log",
+            &format!("-{}", limit),
+            "--name-only",
+            &format!("--notes={}", notes::NOTES_REF),
+            "--format=%x1e%H%x1f%at%x1f%B%x1f%N%x1f",
+        ],
+    )?;
+    let entries = log
+        .split('\x1e')
+        .filter_map(|record| {
+            let mut fields = record.splitn(5, '\x1f');
+            let sha = fields.next()?.trim().to_string();
+            let authored_at = fields.next()?.parse().ok()?;
+            let message = fields.next()?.trim().to_string();
+            let generation = notes::parse(fields.next()?);
+            let files = fields
+                .next()?
+                .lines()
+                .filter(|l| !l.trim().is_empty())
+                .map(|l| l.to_string())
+                .collect();
+            Some(HistoryEntry {
+                sha,
+                authored_at,
+                message,
+                files,
+                generation,
+            })
+        })
+        .filter(|entry| is_autocommit(repo_state, &commit_config, &entry.sha, &entry.message))
+        .collect();
+    Ok(entries)
+}
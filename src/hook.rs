@@ -0,0 +1,119 @@
+use crate::{output_with_timeout, run_command_in_dir, HOOK_MARKER};
+use color_eyre::{eyre::eyre, Result};
+use std::env;
+use std::fs::{canonicalize, File};
+use std::io::Write;
+use std::process::Command;
+use tracing::info;
+
+/// The repo's hooks directory. `--git-path` honors `core.hooksPath`.
+pub fn hooks_dir(repo_path: &std::path::PathBuf) -> Result<std::path::PathBuf> {
+    if !repo_path.join(".git").is_dir() {
+        return Err(eyre!("Path is not a git repo"));
+    }
+    let hooks_dir = run_command_in_dir(repo_path, "git", &["rev-parse", "--git-path", "hooks"])?;
+    Ok(repo_path.join(hooks_dir.trim()))
+}
+
+pub fn hook_path(repo_path: &std::path::PathBuf) -> Result<std::path::PathBuf> {
+    Ok(hooks_dir(repo_path)?.join("prepare-commit-msg"))
+}
+
+/// Run the repo's own `name` hook with `args` the way git would, if it is
+/// installed. Fails if the hook exits non-zero.
+pub fn run_repo_hook(repo_path: &std::path::PathBuf, name: &str, args: &[&str]) -> Result<()> {
+    let hook = hooks_dir(repo_path)?.join(name);
+    if !is_executable(&hook) {
+        return Ok(());
+    }
+    info!("Running the repo's {} hook", name);
+    let output = output_with_timeout(Command::new(&hook).args(args).current_dir(repo_path), None)?;
+    if !output.status.success() {
+        return Err(eyre!(
+            "The {} hook exited with {}: {}",
+            name,
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(())
+}
+
+/// Git skips hooks that aren't executable, and so do we.
+fn is_executable(path: &std::path::Path) -> bool {
+    let Ok(metadata) = std::fs::metadata(path) else {
+        return false;
+    };
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        metadata.is_file() && metadata.permissions().mode() & 0o111 != 0
+    }
+    #[cfg(not(unix))]
+    {
+        metadata.is_file()
+    }
+}
+
+pub fn install_hook(repo_path: &std::path::PathBuf, force: bool) -> Result<()> {
+    let hook = hook_path(repo_path)?;
+    if hook.exists() && !force {
+        let existing = std::fs::read_to_string(&hook)?;
+        if !existing.contains(HOOK_MARKER) {
+            return Err(eyre!(
+                "A prepare-commit-msg hook already exists at {}, use --force to overwrite it",
+                hook.display()
+            ));
+        }
+    }
+
+    let command_path = canonicalize(env::current_exe()?)?
+        .to_string_lossy()
+        .to_string();
+    // Only prefill when git has not been given a message already (-m, -F,
+    // merges, amends), and never block the commit if generation fails.
+    let script = format!(
+        r#"#!/bin/sh
+{marker}
+case "$2" in
+  message|merge|squash|commit) exit 0 ;;
+esac
+msg=$("{command}" message --staged . 2>/dev/null) || exit 0
+[ -n "$msg" ] || exit 0
+{{ printf '%s\n' "$msg"; cat "$1"; }} > "$1.autocommit" && mv "$1.autocommit" "$1"
+"#,
+        marker = HOOK_MARKER,
+        command = command_path,
+    );
+
+    if let Some(parent) = hook.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut file = File::create(&hook)?;
+    file.write_all(script.as_bytes())?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&hook, std::fs::Permissions::from_mode(0o755))?;
+    }
+    info!("Installed hook at {}", hook.display());
+    Ok(())
+}
+
+pub fn uninstall_hook(repo_path: &std::path::PathBuf) -> Result<()> {
+    let hook = hook_path(repo_path)?;
+    if !hook.exists() {
+        return Err(eyre!(
+            "No prepare-commit-msg hook found at {}",
+            hook.display()
+        ));
+    }
+    if !std::fs::read_to_string(&hook)?.contains(HOOK_MARKER) {
+        return Err(eyre!(
+            "The prepare-commit-msg hook at {} was not installed by autocommit",
+            hook.display()
+        ));
+    }
+    std::fs::remove_file(&hook)?;
+    Ok(())
+}
@@ -0,0 +1,128 @@
+use crate::{config::glob_match, run_checked_command_in_dir};
+use color_eyre::Result;
+use std::io::Write;
+use tracing::info;
+
+/// Well-known junk that never belongs in a commit: OS metadata, editor swap
+/// and backup files and Python bytecode, as `.gitignore` lines.
+pub const PATTERNS: &[&str] = &[
+    ".DS_Store",
+    "Thumbs.db",
+    "*.swp",
+    "*.swo",
+    "*~",
+    ".#*",
+    "__pycache__/",
+    "*.pyc",
+];
+
+/// Whether `path` matches the `.gitignore`-style junk `pattern`.
+fn matches(pattern: &str, path: &str) -> bool {
+    match pattern.strip_suffix('/') {
+        Some(dir) => path
+            .split('/')
+            .rev()
+            .skip(1)
+            .any(|component| glob_match(dir, component)),
+        None => path
+            .rsplit('/')
+            .next()
+            .map_or(false, |name| glob_match(pattern, name)),
+    }
+}
+
+/// Pathspecs that keep junk out of status checks and `git add -A`.
+pub fn exclude_pathspecs() -> Vec<String> {
+    PATTERNS
+        .iter()
+        .map(|pattern| match pattern.strip_suffix('/') {
+            Some(dir) => format!(":(exclude,glob)**/{}/**", dir),
+            None => format!(":(exclude,glob)**/{}", pattern),
+        })
+        .collect()
+}
+
+/// Untracked files that aren't ignored but match a junk pattern, with the
+/// pattern they match.
+pub fn find(repo_path: &std::path::PathBuf) -> Result<Vec<(String, &'static str)>> {
+    let untracked = run_checked_command_in_dir(
+        repo_path,
+        "git",
+        &["ls-files", "--others", "--exclude-standard"],
+    )?;
+    Ok(untracked
+        .lines()
+        .filter_map(|file| {
+            PATTERNS
+                .iter()
+                .find(|pattern| matches(pattern, file))
+                .map(|pattern| (file.to_string(), *pattern))
+        })
+        .collect())
+}
+
+/// Report the junk files runs leave out of commits, and with `ignore` append
+/// their patterns to the repo's `.gitignore`.
+pub fn check(repo_path: &std::path::PathBuf, ignore: bool) -> Result<()> {
+    let junk = find(repo_path)?;
+    if junk.is_empty() {
+        return Ok(());
+    }
+    let files = junk
+        .iter()
+        .map(|(file, _)| file.as_str())
+        .collect::<Vec<_>>();
+    let mut patterns = junk.iter().map(|(_, pattern)| *pattern).collect::<Vec<_>>();
+    patterns.sort();
+    patterns.dedup();
+    if !ignore {
+        info!(
+            "Not committing junk files {}, consider adding {} to .gitignore (or use --ignore-junk)",
+            files.join(", "),
+            patterns.join(" ")
+        );
+        return Ok(());
+    }
+
+    let gitignore = repo_path.join(".gitignore");
+    let contents = std::fs::read_to_string(&gitignore).unwrap_or_default();
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&gitignore)?;
+    if !contents.is_empty() && !contents.ends_with('\n') {
+        writeln!(file)?;
+    }
+    for pattern in &patterns {
+        writeln!(file, "{}", pattern)?;
+    }
+    info!(
+        "Added {} to .gitignore for junk files {}",
+        patterns.join(" "),
+        files.join(", ")
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_file_names_anywhere() {
+        assert!(matches(".DS_Store", ".DS_Store"));
+        assert!(matches(".DS_Store", "assets/.DS_Store"));
+        assert!(matches("*.swp", "src/.main.rs.swp"));
+        assert!(!matches("*.swp", "src/main.rs"));
+        // Only the file name is matched, not the directories above it.
+        assert!(!matches("*.pyc", "build.pyc/main.py"));
+    }
+
+    #[test]
+    fn matches_directories() {
+        assert!(matches("__pycache__/", "__pycache__/mod.cpython-311.pyc"));
+        assert!(matches("__pycache__/", "pkg/__pycache__/mod.pyc"));
+        assert!(!matches("__pycache__/", "pkg/__pycache__"));
+        assert!(!matches("__pycache__/", "pkg/mod.py"));
+    }
+}
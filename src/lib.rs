@@ -0,0 +1,119 @@
+//! Automatically commit and push a git repo's changes on a schedule, with
+//! commit messages generated from the diff.
+//!
+//! The `autocommit` binary is a thin CLI over this crate. To embed a run in
+//! another tool, build an [`Autocommit`] (optionally with your own
+//! [`MessageGenerator`]) and call [`Autocommit::run`] with a [`RepoConfig`].
+//! The [`schedule`] module manages the crontab entries, [`git`] wraps the git
+//! commands runs use, and [`config`] reads `~/.config/autocommit/config.toml`.
+
+pub mod auth;
+pub mod backup;
+pub mod changelog;
+pub mod completions;
+pub mod config;
+pub mod discover;
+pub mod doctor;
+pub mod env_file;
+pub mod git;
+pub mod history;
+pub mod hook;
+pub mod junk;
+pub mod lock;
+pub mod logs;
+pub mod message;
+pub mod metrics;
+pub mod notes;
+pub mod notify;
+pub mod options;
+pub mod pr;
+pub mod prune;
+pub mod ratelimit;
+pub mod run;
+pub mod schedule;
+pub mod secrets;
+pub mod snapshot;
+pub mod split;
+pub mod squash;
+pub mod state;
+pub mod summary;
+pub mod tags;
+pub mod telemetry;
+pub mod trailers;
+pub mod ui;
+pub mod undo;
+pub mod verify;
+
+pub use git::*;
+pub use hook::*;
+pub use message::*;
+pub use options::*;
+pub use run::*;
+pub use schedule::*;
+
+pub static COMMAND_NAME: &str = "autocommit";
+/// Runs skip a repo while this file exists at its root.
+pub static SKIP_FILE: &str = ".autocommit-skip";
+pub static HOOK_MARKER: &str = "# Installed by autocommit";
+
+/// A repo to run on, and how.
+#[derive(Debug, Default)]
+pub struct RepoConfig {
+    pub path: std::path::PathBuf,
+    pub options: RunOptions,
+}
+
+/// Runs autocommit on repos, generating messages with its
+/// [`MessageGenerator`].
+#[derive(Default)]
+pub struct Autocommit {
+    /// `None` to use the [`default_generator`] from the config.
+    generator: Option<Box<dyn MessageGenerator>>,
+    /// The default generator, created on first use and reused for every
+    /// repo, so its connections are shared across runs.
+    default_generator: tokio::sync::OnceCell<Box<dyn MessageGenerator>>,
+}
+
+impl Autocommit {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Generate messages with `generator` instead of the configured one.
+    pub fn with_generator(generator: impl MessageGenerator + 'static) -> Self {
+        Self {
+            generator: Some(Box::new(generator)),
+            ..Self::default()
+        }
+    }
+
+    async fn generator(&self) -> color_eyre::Result<&dyn MessageGenerator> {
+        match &self.generator {
+            Some(generator) => Ok(generator.as_ref()),
+            None => Ok(self
+                .default_generator
+                .get_or_try_init(|| async { default_generator() })
+                .await?
+                .as_ref()),
+        }
+    }
+
+    /// Commit, and depending on the push policy push, the repo's changes once.
+    /// The result is reported to the configured notifications and metrics and
+    /// recorded in the state file, like a scheduled run.
+    pub async fn run(&self, config: RepoConfig) -> color_eyre::Result<RunOutcome> {
+        run_and_report(config.path, &config.options, self.generator().await?).await
+    }
+
+    /// Summarize the repo's changes since `since` (a revision, by default
+    /// where the autocommits leading up to HEAD start) without committing,
+    /// e.g. for a standup update.
+    pub async fn summarize(
+        &self,
+        path: &std::path::PathBuf,
+        since: Option<&str>,
+    ) -> color_eyre::Result<String> {
+        let (summary, _) = summary::summarize(path, self.generator().await?, since).await?;
+        Ok(summary)
+    }
+}
@@ -0,0 +1,117 @@
+use color_eyre::Result;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::time::Duration;
+use tracing::{debug, warn};
+
+/// Locks older than this are assumed to be left over from a crashed run.
+const STALE_LOCK_AGE: Duration = Duration::from_secs(60 * 60);
+
+/// Held for the duration of a run so that overlapping runs on the same repo
+/// (e.g. a slow push outlasting the cron interval) don't race on the index.
+/// The lock file is removed on drop.
+pub struct RepoLock {
+    path: std::path::PathBuf,
+}
+
+impl RepoLock {
+    /// Take the repo's lock, or return `None` if another run holds it.
+    pub fn acquire(repo_path: &std::path::Path) -> Result<Option<RepoLock>> {
+        Self::acquire_file(repo_path.join(".git").join("autocommit.lock"))
+    }
+
+    /// Take the lock file at `path`, for other state shared between
+    /// processes, or return `None` if another process holds it.
+    pub fn acquire_file(path: std::path::PathBuf) -> Result<Option<RepoLock>> {
+        for _ in 0..2 {
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(mut file) => {
+                    write!(file, "{}", std::process::id())?;
+                    return Ok(Some(RepoLock { path }));
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => match is_stale(&path) {
+                    Ok(false) => return Ok(None),
+                    Ok(true) => {
+                        warn!("Removing stale lock {}", path.display());
+                        // Another run may have removed it first.
+                        if let Err(e) = std::fs::remove_file(&path) {
+                            if e.kind() != std::io::ErrorKind::NotFound {
+                                return Err(e.into());
+                            }
+                        }
+                    }
+                    // The holder released the lock while we looked at it.
+                    Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                    Err(e) => return Err(e.into()),
+                },
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(None)
+    }
+}
+
+impl Drop for RepoLock {
+    fn drop(&mut self) {
+        if let Err(e) = std::fs::remove_file(&self.path) {
+            warn!("Could not remove lock {}: {}", self.path.display(), e);
+        }
+    }
+}
+
+/// A lock is stale if it is old or the process that took it is gone.
+fn is_stale(path: &std::path::Path) -> std::io::Result<bool> {
+    let age = std::fs::metadata(path)?
+        .modified()?
+        .elapsed()
+        .unwrap_or_default();
+    if age > STALE_LOCK_AGE {
+        return Ok(true);
+    }
+    let pid = std::fs::read_to_string(path)?;
+    let pid = pid.trim();
+    if pid.is_empty() {
+        // The owner may not have written its pid yet.
+        return Ok(false);
+    }
+    Ok(!process_alive(pid))
+}
+
+#[cfg(unix)]
+fn process_alive(pid: &str) -> bool {
+    // `kill -0` only checks whether the process exists.
+    let alive = std::process::Command::new("kill")
+        .args(["-0", pid])
+        .stderr(std::process::Stdio::null())
+        .status()
+        .map(|status| status.success())
+        .unwrap_or(true);
+    debug!("Lock owner {} alive: {}", pid, alive);
+    alive
+}
+
+#[cfg(not(unix))]
+fn process_alive(_pid: &str) -> bool {
+    // Without a cheap liveness check, rely on the lock's age alone.
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn released_lock_is_retried() {
+        let path =
+            std::env::temp_dir().join(format!("autocommit-lock-test-{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let kind = is_stale(&path).unwrap_err().kind();
+        assert_eq!(kind, std::io::ErrorKind::NotFound);
+
+        let lock = RepoLock::acquire_file(path.clone()).unwrap();
+        assert!(lock.is_some());
+        assert!(RepoLock::acquire_file(path.clone()).unwrap().is_none());
+        drop(lock);
+        assert!(RepoLock::acquire_file(path).unwrap().is_some());
+    }
+}
@@ -0,0 +1,215 @@
+use crate::config::{state_dir, LoggingConfig};
+use crate::{list, run_checked_command_in_dir, write_autocommits};
+use color_eyre::{eyre::eyre, Result};
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::time::Duration;
+use tracing::info;
+
+const FOLLOW_INTERVAL: Duration = Duration::from_millis(500);
+/// Where cron used to redirect run output, inside the repo.
+pub const LEGACY_LOG: &str = ".autocommit_log";
+
+/// `~/.local/state/autocommit/logs/<repo>.log`, where `<repo>` is the repo's
+/// path with separators replaced so every repo gets its own file.
+pub fn log_path(repo_path: &std::path::Path) -> Result<std::path::PathBuf> {
+    let name = repo_path
+        .to_string_lossy()
+        .trim_start_matches(&['/', '\\'][..])
+        .replace(&['/', '\\', ':'][..], "_");
+    Ok(state_dir()?.join("logs").join(format!("{}.log", name)))
+}
+
+/// Every log file of the repo: the current one, rotated ones and the legacy
+/// log migrated from the repo.
+pub fn files(repo_path: &std::path::Path) -> Result<Vec<std::path::PathBuf>> {
+    let path = log_path(repo_path)?;
+    let (Some(dir), Some(name)) = (path.parent(), path.file_name()) else {
+        return Ok(Vec::new());
+    };
+    let name = name.to_string_lossy().to_string();
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Ok(Vec::new());
+    };
+    let mut files = entries
+        .filter_map(|e| e.ok())
+        .filter(|entry| {
+            let file_name = entry.file_name().to_string_lossy().to_string();
+            file_name == name || file_name.starts_with(&format!("{}.", name))
+        })
+        .map(|entry| entry.path())
+        .collect::<Vec<_>>();
+    files.sort();
+    Ok(files)
+}
+
+fn rotated_path(path: &std::path::Path, index: u32) -> std::path::PathBuf {
+    let mut rotated = path.as_os_str().to_owned();
+    rotated.push(format!(".{}", index));
+    std::path::PathBuf::from(rotated)
+}
+
+/// Rotate the repo's log if it grew too large and open it for appending.
+pub fn open(repo_path: &std::path::Path, config: &LoggingConfig) -> Result<File> {
+    let path = log_path(repo_path)?;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    rotate(&path, config)?;
+    Ok(OpenOptions::new().create(true).append(true).open(&path)?)
+}
+
+/// Shift `<log>` to `<log>.1`, `<log>.1` to `<log>.2` and so on once it is
+/// larger than the configured size, and drop rotated logs that are too old or
+/// too many.
+fn rotate(path: &std::path::Path, config: &LoggingConfig) -> Result<()> {
+    let too_large = std::fs::metadata(path)
+        .map(|m| m.len() >= config.max_size_bytes)
+        .unwrap_or(false);
+    if too_large {
+        for index in (1..config.max_files).rev() {
+            let from = rotated_path(path, index);
+            if from.exists() {
+                std::fs::rename(&from, rotated_path(path, index + 1))?;
+            }
+        }
+        std::fs::rename(path, rotated_path(path, 1))?;
+    }
+
+    let max_age = Duration::from_secs(config.max_age_days * 24 * 60 * 60);
+    for index in 1.. {
+        let rotated = rotated_path(path, index);
+        let Ok(metadata) = std::fs::metadata(&rotated) else {
+            break;
+        };
+        let expired = metadata
+            .modified()?
+            .elapsed()
+            .map_or(false, |age| age > max_age);
+        if index > config.max_files || expired {
+            std::fs::remove_file(&rotated)?;
+        }
+    }
+    Ok(())
+}
+
+/// Delete logs nothing was written to for `max_age_days`, e.g. those of repos
+/// whose autocommit was deleted.
+pub fn prune(config: &LoggingConfig) -> Result<()> {
+    let Ok(entries) = std::fs::read_dir(state_dir()?.join("logs")) else {
+        return Ok(());
+    };
+    let max_age = Duration::from_secs(config.max_age_days * 24 * 60 * 60);
+    for entry in entries.filter_map(|e| e.ok()) {
+        let expired = entry
+            .metadata()?
+            .modified()?
+            .elapsed()
+            .map_or(false, |age| age > max_age);
+        if expired {
+            std::fs::remove_file(entry.path())?;
+        }
+    }
+    Ok(())
+}
+
+/// Move a repo off the old in-repo `.autocommit_log`: drop the `>>`
+/// redirections from the crontab, stop tracking and ignoring the file, and
+/// keep its contents next to the repo's log as `<log>.legacy`.
+pub fn migrate(repo_path: &std::path::PathBuf) -> Result<()> {
+    let legacy = repo_path.join(LEGACY_LOG);
+    if !legacy.exists() {
+        return Ok(());
+    }
+
+    let mut autocommits = list()?;
+    let mut redirected = false;
+    for autocommit in autocommits.iter_mut() {
+        if let Some(redirect) = autocommit.args.iter().position(|a| a == ">>") {
+            autocommit.args.truncate(redirect);
+            redirected = true;
+        }
+    }
+    if redirected {
+        write_autocommits(&autocommits)?;
+    }
+
+    if run_checked_command_in_dir(
+        repo_path,
+        "git",
+        &["ls-files", "--error-unmatch", LEGACY_LOG],
+    )
+    .is_ok()
+    {
+        run_checked_command_in_dir(repo_path, "git", &["rm", "--cached", "--quiet", LEGACY_LOG])?;
+    }
+    let gitignore = repo_path.join(".gitignore");
+    if let Ok(contents) = std::fs::read_to_string(&gitignore) {
+        let is_legacy = |line: &str| line.trim().trim_start_matches('/') == LEGACY_LOG;
+        if contents.lines().any(is_legacy) {
+            let kept = contents
+                .lines()
+                .filter(|line| !is_legacy(line))
+                .map(|line| format!("{}\n", line))
+                .collect::<String>();
+            std::fs::write(&gitignore, kept)?;
+        }
+    }
+
+    let mut destination = log_path(repo_path)?.into_os_string();
+    destination.push(".legacy");
+    let destination = std::path::PathBuf::from(destination);
+    if let Some(parent) = destination.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::copy(&legacy, &destination)?;
+    std::fs::remove_file(&legacy)?;
+    info!(
+        "Moved {} to {}, runs log outside the repo now",
+        legacy.display(),
+        destination.display()
+    );
+    Ok(())
+}
+
+/// Print the repo's log, optionally only its last `lines` lines, and keep
+/// printing new output if `follow` is set.
+pub fn show(repo_path: &std::path::Path, lines: Option<usize>, follow: bool) -> Result<()> {
+    let path = log_path(repo_path)?;
+    if !path.exists() && !follow {
+        return Err(eyre!("No logs for {} yet", repo_path.display()));
+    }
+
+    let contents = std::fs::read_to_string(&path).unwrap_or_default();
+    let all_lines = contents.lines().collect::<Vec<_>>();
+    let skip = lines.map_or(0, |lines| all_lines.len().saturating_sub(lines));
+    let mut stdout = std::io::stdout();
+    for line in &all_lines[skip..] {
+        writeln!(stdout, "{}", line)?;
+    }
+    if !follow {
+        return Ok(());
+    }
+
+    let mut position = contents.len() as u64;
+    loop {
+        std::thread::sleep(FOLLOW_INTERVAL);
+        let Ok(metadata) = std::fs::metadata(&path) else {
+            continue;
+        };
+        if metadata.len() < position {
+            // The log was rotated, start over on the new file.
+            position = 0;
+        }
+        if metadata.len() == position {
+            continue;
+        }
+        let mut file = File::open(&path)?;
+        file.seek(SeekFrom::Start(position))?;
+        let mut new_output = String::new();
+        file.read_to_string(&mut new_output)?;
+        position += new_output.len() as u64;
+        write!(stdout, "{}", new_output)?;
+        stdout.flush()?;
+    }
+}
@@ -1,18 +1,22 @@
-use clap::{Parser, Subcommand};
+use autocommit_core::{
+    auth, backup, catchup, changelog, completions, config, create, default_generator, discover,
+    doctor, dry_run_summary, env_file, exit_code, expand_glob, history, install_hook, list, lock,
+    logs, message, prune, push, run_summary, secrets, squash, status, telemetry, ui, undo,
+    uninstall_hook, verify, write_autocommits, Autocommit, DiffSource, RepoConfig, RepoOptions,
+    RunOptions, ScheduleArgs, EXIT_CODES_HELP,
+};
+use clap::{CommandFactory, Parser, Subcommand};
 use color_eyre::{eyre::eyre, Report, Result};
-use derive_more::Display;
-use openai_api_rs::v1::api::Client;
-use openai_api_rs::v1::chat_completion::{self, ChatCompletionRequest};
-use std::fs::{canonicalize, File, OpenOptions};
-use std::io::{Read, Write};
-use std::process::Command;
-use std::{env, process::Stdio};
-use tracing::{debug, info};
-use tracing_subscriber::EnvFilter;
-
-static COMMAND_NAME: &str = "autocommit";
-
-fn setup() -> Result<(), Report> {
+use std::env;
+use std::fs::{canonicalize, File};
+use std::io::Read;
+use tracing::{debug, error, info, warn};
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::prelude::*;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::{EnvFilter, Layer};
+
+fn setup(cli: &Cli) -> Result<Option<sentry::ClientInitGuard>, Report> {
     if std::env::var("RUST_LIB_BACKTRACE").is_err() {
         std::env::set_var("RUST_LIB_BACKTRACE", "1")
     }
@@ -21,11 +25,88 @@ fn setup() -> Result<(), Report> {
     if std::env::var("RUST_LOG").is_err() {
         std::env::set_var("RUST_LOG", "info")
     }
-    tracing_subscriber::fmt::fmt()
-        .with_env_filter(EnvFilter::from_default_env())
+    // A broken config file only fails the commands that need it, logging
+    // and telemetry fall back to the defaults so `doctor` can point it out.
+    let (config, config_error) = match config::Config::load() {
+        Ok(config) => (config, None),
+        Err(e) => (config::Config::default(), Some(e)),
+    };
+
+    // Set up before logging, its panic hook chains to color_eyre's.
+    let sentry_dsn = env::var("SENTRY_DSN")
+        .ok()
+        .or_else(|| config.sentry.as_ref().and_then(|sentry| sentry.dsn.clone()));
+    let sentry = telemetry::init_sentry(
+        sentry_dsn,
+        config
+            .sentry
+            .as_ref()
+            .and_then(|sentry| sentry.environment.clone()),
+    )?;
+
+    // The standard OTLP variable takes precedence over the config file.
+    let otlp_endpoint = match env::var("OTEL_EXPORTER_OTLP_ENDPOINT") {
+        Ok(endpoint) => Some(endpoint),
+        Err(_) => config
+            .tracing
+            .as_ref()
+            .and_then(|tracing| tracing.otlp_endpoint.clone()),
+    };
+
+    let log_format = match env::var("AUTOCOMMIT_LOG_FORMAT") {
+        Ok(format) => format.parse::<config::LogFormat>().map_err(|e| eyre!(e))?,
+        Err(_) => config.logging.format,
+    };
+    let json_logs = log_format == config::LogFormat::Json;
+
+    // Runs log to a per-repo file. Unattended runs only log there, so cron
+    // output is limited to errors.
+    let log_file = match &cli.command {
+        Commands::Run { path, .. } => match canonicalize(path) {
+            Ok(path) => Some(logs::open(&path, &config.logging)?),
+            Err(_) => None,
+        },
+        _ => None,
+    };
+    let quiet = matches!(cli.command, Commands::Run { quiet: true, .. });
+    let log_to_stderr = !quiet && (log_file.is_none() || console::Term::stderr().is_term());
+
+    // Log to stderr so stdout stays clean for commands like `message` whose
+    // output is consumed by scripts.
+    tracing_subscriber::registry()
+        .with(EnvFilter::from_default_env())
+        .with(log_to_stderr.then(|| fmt_layer(json_logs, std::io::stderr, true)))
+        .with(log_file.map(|file| fmt_layer(json_logs, std::sync::Mutex::new(file), false)))
+        .with(telemetry::otlp_layer(otlp_endpoint)?)
+        .with(sentry.is_some().then(telemetry::sentry_layer))
         .init();
+    if let Some(e) = config_error {
+        warn!(error = %format!("{:#}", e), "Could not load the config, using the defaults");
+    }
 
-    Ok(())
+    Ok(sentry)
+}
+
+fn fmt_layer<S, W>(json: bool, writer: W, ansi: bool) -> Box<dyn Layer<S> + Send + Sync>
+where
+    S: tracing::Subscriber + for<'span> LookupSpan<'span>,
+    W: for<'writer> MakeWriter<'writer> + Send + Sync + 'static,
+{
+    if json {
+        Box::new(
+            tracing_subscriber::fmt::layer()
+                .json()
+                .with_current_span(true)
+                .with_span_list(true)
+                .with_writer(writer),
+        )
+    } else {
+        Box::new(
+            tracing_subscriber::fmt::layer()
+                .with_ansi(ansi)
+                .with_writer(writer),
+        )
+    }
 }
 
 /// Search for a pattern in a file and display the lines that contain it.
@@ -35,303 +116,726 @@ fn setup() -> Result<(), Report> {
 struct Cli {
     #[command(subcommand)]
     command: Commands,
+
+    /// Print machine-readable JSON on stdout for `run`, `list` and `status`.
+    #[clap(long, global = true)]
+    json: bool,
 }
 
 #[derive(Subcommand, Debug)]
 enum Commands {
+    /// Commit a repo's changes with a generated message and push them.
+    #[command(after_help = EXIT_CODES_HELP)]
     Run {
         path: std::path::PathBuf,
+
+        /// Show what would be committed and pushed without changing anything.
+        #[clap(long)]
+        dry_run: bool,
+
+        /// Use this commit message instead of generating one.
+        #[clap(long, short = 'm', conflicts_with = "message_file")]
+        message: Option<String>,
+
+        /// Read the commit message from a file instead of generating one.
+        #[clap(long, short = 'F')]
+        message_file: Option<std::path::PathBuf>,
+
+        /// Review the message in $EDITOR before committing; an empty message
+        /// aborts. Not available with --snapshot-branch.
+        #[clap(long, short = 'e', conflicts_with = "snapshot_branch")]
+        edit: bool,
+
+        /// Prompt to accept, regenerate or edit the message and pick files to
+        /// commit. Not available with --snapshot-branch.
+        #[clap(long, short = 'i', conflicts_with = "snapshot_branch")]
+        interactive: bool,
+
+        /// Only print the summary of the run, no log lines.
+        #[clap(long, short = 'q')]
+        quiet: bool,
+
+        /// Instead of committing, print a summary of what changed since this
+        /// revision, by default since the autocommits leading up to HEAD started.
+        #[clap(
+            long,
+            value_name = "REV",
+            num_args = 0..=1,
+            conflicts_with_all = ["dry_run", "message", "message_file", "edit", "interactive"]
+        )]
+        since: Option<Option<String>>,
+
+        #[command(flatten)]
+        repo_options: RepoOptions,
     },
     Create {
+        /// Path to the git repo, can be repeated or a `*` pattern.
+        #[clap(long, short = 'p', required_unless_present = "discover")]
+        path: Vec<std::path::PathBuf>,
+
+        #[command(flatten)]
+        schedule: ScheduleArgs,
+
+        #[command(flatten)]
+        discover: discover::DiscoverArgs,
+
+        #[command(flatten)]
+        repo_options: RepoOptions,
+    },
+    /// Register repos newly cloned under directories added with
+    /// `create --discover --sync`. Runs do this hourly on their own.
+    SyncDiscover,
+    /// Squash consecutive autocommits into a single commit.
+    Squash {
         /// Path to the git repo.
-        #[clap(long, short = 'p')]
         path: std::path::PathBuf,
 
-        /// Minutes between autocommits
-        #[clap(long, short = 'f')]
-        frequency: u32,
+        /// Squash autocommits made since `today`, `yesterday` or a `YYYY-MM-DD` date.
+        #[clap(long, default_value = "today")]
+        since: String,
+
+        /// Allow squashing autocommits that were already pushed, and force
+        /// push the result with `--force-with-lease`.
+        #[clap(long)]
+        force_with_lease: bool,
+    },
+    /// Remove the most recent autocommit, keeping its changes staged.
+    Undo {
+        /// Path to the git repo.
+        path: std::path::PathBuf,
+
+        /// Allow undoing an autocommit that was already pushed, and force push
+        /// with `--force-with-lease`.
+        #[clap(long)]
+        force_with_lease: bool,
+    },
+    /// List the commits autocommit made, with the files they touched.
+    History {
+        /// Path to the git repo.
+        path: std::path::PathBuf,
+
+        /// How many commits back to look.
+        #[clap(short = 'n', long, default_value_t = 100)]
+        limit: usize,
+    },
+    /// Check that recent autocommits made it to the remote.
+    Verify {
+        /// Only check this repo instead of every configured one.
+        path: Option<std::path::PathBuf>,
+
+        /// How many of the most recent autocommits to check per repo.
+        #[clap(short = 'n', long, default_value_t = 50)]
+        limit: usize,
+
+        /// Push the branches holding autocommits that are missing on the remote.
+        #[clap(long)]
+        repush: bool,
+    },
+    /// Remove the schedules, state and logs of repos that no longer exist.
+    Prune {
+        /// Only show what would be removed.
+        #[clap(long)]
+        dry_run: bool,
+    },
+    /// Print a Markdown changelog written from the autocommits since a date or tag.
+    Changelog {
+        /// Path to the git repo.
+        path: std::path::PathBuf,
+
+        /// Start after this tag (or revision), or at `today`, `yesterday` or a
+        /// `YYYY-MM-DD` date.
+        #[clap(long)]
+        since: String,
+
+        /// Include commits not made by autocommit.
+        #[clap(long)]
+        manual: bool,
+    },
+    /// Push any commits that were held back by the push policy.
+    Push {
+        /// Path to the git repo.
+        path: std::path::PathBuf,
     },
     /// List currently configured autocommits.
     List,
+    /// Run the autocommits whose schedule was missed while the machine was
+    /// off or asleep, e.g. from a login item.
+    Catchup {
+        /// Only show which repos missed a run.
+        #[clap(long)]
+        dry_run: bool,
+    },
+    /// Show the state and last run of configured autocommits.
+    Status {
+        /// Only show this repo instead of every configured one.
+        path: Option<std::path::PathBuf>,
+    },
     Delete {
-        /// Path of autocommit repo to delete.
+        /// Paths of autocommit repos to delete, `*` patterns match configured repos.
+        #[clap(required = true)]
+        paths: Vec<std::path::PathBuf>,
+    },
+    /// Print a generated commit message for the current diff without committing.
+    Message {
+        /// Use only staged changes (the default).
+        #[clap(long, conflicts_with = "all")]
+        staged: bool,
+
+        /// Use all changes in the working tree relative to HEAD.
+        #[clap(long)]
+        all: bool,
+
+        /// Path to the git repo.
+        #[clap(default_value = ".")]
+        path: std::path::PathBuf,
+    },
+    /// Manage the `prepare-commit-msg` git hook.
+    Hook {
+        #[command(subcommand)]
+        command: HookCommands,
+    },
+    /// Manage the OpenAI key stored in the OS keyring.
+    Auth {
+        #[command(subcommand)]
+        command: AuthCommands,
+    },
+    /// Manage secrets stored encrypted in the config file.
+    Secret {
+        #[command(subcommand)]
+        command: SecretCommands,
+    },
+    /// Print the configured autocommits as TOML, for `import` on another machine.
+    Export,
+    /// Add the autocommits from an `export`, asking where repos that moved live.
+    Import {
+        /// File written by `export`, `-` for stdin.
+        file: std::path::PathBuf,
+    },
+    /// Check that everything runs need works, and suggest fixes.
+    Doctor {
+        /// Only check this repo instead of every configured one.
+        path: Option<std::path::PathBuf>,
+    },
+    /// Print a completion script for the given shell, e.g.
+    /// `autocommit completions zsh > ~/.zfunc/_autocommit`.
+    Completions { shell: clap_complete::Shell },
+    /// Print the configured repo paths, for shell completion.
+    #[command(hide = true)]
+    CompleteRepos,
+    /// Interactive dashboard of all configured autocommits.
+    Ui,
+    /// Show the log of an autocommit's runs.
+    Logs {
+        /// Path to the git repo.
+        path: std::path::PathBuf,
+
+        /// Only show the last N lines.
+        #[clap(long, short = 'n')]
+        lines: Option<usize>,
+
+        /// Keep printing new log lines as they are written.
+        #[clap(long, short = 'f')]
+        follow: bool,
+    },
+}
+
+impl Commands {
+    /// The repo the command works on, for the ones that take one.
+    fn repo_path(&self) -> Option<&std::path::PathBuf> {
+        match self {
+            Commands::Run { path, .. }
+            | Commands::Squash { path, .. }
+            | Commands::Undo { path, .. }
+            | Commands::History { path, .. }
+            | Commands::Changelog { path, .. }
+            | Commands::Push { path, .. }
+            | Commands::Message { path, .. }
+            | Commands::Logs { path, .. } => Some(path),
+            Commands::Verify { path, .. }
+            | Commands::Status { path }
+            | Commands::Doctor { path } => path.as_ref(),
+            _ => None,
+        }
+    }
+}
+
+/// Load the env files and decrypt the config's secrets, so every command that
+/// calls the model or a forge finds its keys.
+fn load_env(command: &Commands) -> Result<()> {
+    let repo = command
+        .repo_path()
+        .and_then(|path| canonicalize(path).ok())
+        .filter(|path| path.join(".git").is_dir());
+    env_file::load(repo.as_ref())?;
+    // Commands that need the config report it not loading themselves.
+    if let Ok(config) = config::Config::load() {
+        secrets::load(&config)?;
+    }
+    Ok(())
+}
+
+#[derive(Subcommand, Debug)]
+enum AuthCommands {
+    /// Store the OpenAI key in the keyring, prompting for it if not given.
+    Set {
+        /// The key, prefer the prompt to keep it out of your shell history.
+        key: Option<String>,
+    },
+    /// Remove the OpenAI key from the keyring.
+    Unset,
+}
+
+#[derive(Subcommand, Debug)]
+enum SecretCommands {
+    /// Encrypt a value and print the line to add under `[secrets]` in the
+    /// config, generating the key file on first use.
+    Encrypt {
+        /// Environment variable the secret is loaded into, e.g. `OPENAI_API_KEY`.
+        name: String,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum HookCommands {
+    /// Install a `prepare-commit-msg` hook that prefills generated messages.
+    Install {
+        /// Overwrite an existing hook not installed by autocommit.
+        #[clap(long)]
+        force: bool,
+
+        /// Path to the git repo.
+        #[clap(default_value = ".")]
+        path: std::path::PathBuf,
+    },
+    /// Remove the `prepare-commit-msg` hook installed by autocommit.
+    Uninstall {
+        /// Path to the git repo.
+        #[clap(default_value = ".")]
         path: std::path::PathBuf,
     },
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    setup()?;
     let cli = Cli::parse();
+    // Sentry reports until this is dropped.
+    let _sentry = setup(&cli)?;
+
+    let result = dispatch(&cli).await;
+    telemetry::shutdown();
+    result
+}
 
+async fn dispatch(cli: &Cli) -> Result<()> {
+    if let Err(e) = load_env(&cli.command) {
+        warn!("Could not load the env files or secrets: {:#}", e);
+    }
     match &cli.command {
-        Commands::Run { path } => {
+        Commands::Run {
+            path,
+            dry_run,
+            message,
+            message_file,
+            edit,
+            interactive,
+            quiet,
+            since,
+            repo_options,
+        } => {
             let path = canonicalize(path)?;
+            if let Some(since) = since {
+                let summary = Autocommit::new().summarize(&path, since.as_deref()).await?;
+                if cli.json {
+                    println!(
+                        "{}",
+                        serde_json::json!({ "repo": path, "summary": summary })
+                    );
+                } else {
+                    println!("{}", summary);
+                }
+                return Ok(());
+            }
             info!("Running {}", path.display());
-            run(path.to_path_buf()).await?;
+            let message = match message_file {
+                Some(file) => Some(std::fs::read_to_string(file)?.trim().to_string()),
+                None => message.clone(),
+            };
+            if message.as_deref() == Some("") {
+                return Err(eyre!("Commit message is empty"));
+            }
+            let options = RunOptions {
+                dry_run: *dry_run,
+                message,
+                edit: *edit,
+                interactive: *interactive,
+                repo: repo_options.clone(),
+            };
+            let start = std::time::Instant::now();
+            let result = Autocommit::new()
+                .run(RepoConfig {
+                    path: path.clone(),
+                    options,
+                })
+                .await;
+            if !*dry_run {
+                if let Err(e) = discover::sync_if_due() {
+                    error!(error = %e, "Discovering new repos failed");
+                }
+            }
+            if cli.json {
+                let mut output = match &result {
+                    Ok(outcome) => serde_json::to_value(outcome)?,
+                    Err(e) => serde_json::json!({
+                        "result": "error",
+                        "error": format!("{:#}", e),
+                    }),
+                };
+                output["repo"] = serde_json::json!(path);
+                println!("{}", output);
+            } else if *dry_run {
+                if let Some(summary) = result.as_ref().ok().and_then(dry_run_summary) {
+                    println!("{}", summary);
+                }
+            } else if let Some(summary) = run_summary(&path, &result, start.elapsed()) {
+                println!("{}", summary);
+            }
+            let code = exit_code(&result);
+            if code != 0 {
+                if let Err(e) = &result {
+                    if !*quiet {
+                        eprintln!("Error: {:?}", e);
+                    }
+                }
+                telemetry::shutdown();
+                std::process::exit(code);
+            }
+        }
+        Commands::Create {
+            path,
+            schedule,
+            discover,
+            repo_options,
+        } => match &discover.discover {
+            None => {
+                let paths = path
+                    .iter()
+                    .map(|path| expand_glob(path))
+                    .collect::<Result<Vec<_>>>()?
+                    .concat();
+                if paths.is_empty() {
+                    return Err(eyre!("No repos match {:?}", path));
+                }
+                let mut failed = 0;
+                for path in &paths {
+                    if let Err(e) = create(path, schedule.frequency(), repo_options) {
+                        if paths.len() == 1 {
+                            return Err(e);
+                        }
+                        error!(path = %path.display(), error = %e, "Could not create autocommit");
+                        failed += 1;
+                    }
+                }
+                if failed > 0 {
+                    return Err(eyre!(
+                        "{} of {} autocommits could not be created",
+                        failed,
+                        paths.len()
+                    ));
+                }
+            }
+            Some(dir) => {
+                let root = discover::DiscoverRoot {
+                    dir: canonicalize(dir)?.to_string_lossy().to_string(),
+                    frequency: schedule.frequency(),
+                    depth: discover.depth,
+                    ignore: discover.ignore.clone(),
+                    options: repo_options.to_args(),
+                };
+                let added = discover::register(&root)?;
+                info!("Created {} autocommits under {}", added, root.dir);
+                if discover.sync {
+                    discover::watch(root)?;
+                }
+            }
+        },
+        Commands::SyncDiscover => {
+            let added = discover::sync()?;
+            info!("Created {} autocommits", added);
+        }
+        Commands::Squash {
+            path,
+            since,
+            force_with_lease,
+        } => {
+            let path = canonicalize(path)?;
+            info!("Squashing {}", path.display());
+            let since = squash::parse_since(since)?;
+            let _lock = lock::RepoLock::acquire(&path)?
+                .ok_or_else(|| eyre!("A run is in progress on {}", path.display()))?;
+            let generator = default_generator()?;
+            if let Some(sha) =
+                squash::squash(&path, since, *force_with_lease, generator.as_ref()).await?
+            {
+                info!("Squashed into {}", sha);
+            }
+        }
+        Commands::Undo {
+            path,
+            force_with_lease,
+        } => {
+            let path = canonicalize(path)?;
+            let _lock = lock::RepoLock::acquire(&path)?
+                .ok_or_else(|| eyre!("A run is in progress on {}", path.display()))?;
+            let sha = undo::undo(&path, *force_with_lease)?;
+            println!("Undid autocommit {}, its changes are staged", sha);
+        }
+        Commands::History { path, limit } => {
+            let path = canonicalize(path)?;
+            let entries = history::history(&path, *limit)?;
+            if cli.json {
+                println!("{}", serde_json::to_string(&entries)?);
+                return Ok(());
+            }
+            for entry in entries {
+                println!("{}", entry);
+            }
+        }
+        Commands::Verify {
+            path,
+            limit,
+            repush,
+        } => {
+            let paths = match path {
+                Some(path) => vec![canonicalize(path)?],
+                None => list()?
+                    .iter()
+                    .map(|autocommit| std::path::PathBuf::from(autocommit.repo_path()))
+                    .collect(),
+            };
+            let verifications = paths
+                .iter()
+                .map(|path| verify::verify(path, *limit, *repush))
+                .collect::<Result<Vec<_>>>()?;
+            if cli.json {
+                println!("{}", serde_json::to_string(&verifications)?);
+            } else {
+                for verification in &verifications {
+                    println!("{}", verification);
+                }
+            }
+            let unpushed = verifications
+                .iter()
+                .map(|verification| verification.unpushed.len())
+                .sum::<usize>();
+            if unpushed > 0 {
+                return Err(eyre!(
+                    "{} autocommits are not on their remote, `verify --repush` pushes them",
+                    unpushed
+                ));
+            }
+        }
+        Commands::Prune { dry_run } => {
+            prune::prune(*dry_run)?;
         }
-        Commands::Create { path, frequency } => {
-            create(path, *frequency)?;
+        Commands::Changelog {
+            path,
+            since,
+            manual,
+        } => {
+            let path = canonicalize(path)?;
+            let generator = default_generator()?;
+            let (changelog, _) =
+                changelog::changelog(&path, generator.as_ref(), since, *manual).await?;
+            println!("{}", changelog);
+        }
+        Commands::Push { path } => {
+            let path = canonicalize(path)?;
+            info!("Pushing {}", path.display());
+            push(&path)?;
         }
+        Commands::Catchup { dry_run } => catchup(*dry_run)?,
         Commands::List => {
             info!("Listing");
             let autocommits = list()?;
+            let now = chrono::Local::now();
+            if cli.json {
+                let output = autocommits
+                    .iter()
+                    .map(|autocommit| {
+                        serde_json::json!({
+                            "repo": autocommit.repo_path(),
+                            "schedule": autocommit.frequency.join(" "),
+                            "description": autocommit.describe(),
+                            "next_run": autocommit
+                                .next_run(now)
+                                .filter(|_| !autocommit.paused)
+                                .map(|at| at.to_rfc3339()),
+                            "paused": autocommit.paused,
+                            "problems": autocommit.problems(),
+                            "command": autocommit.shell_command(),
+                        })
+                    })
+                    .collect::<Vec<_>>();
+                println!("{}", serde_json::Value::Array(output));
+                return Ok(());
+            }
             info!("Found {} autocommits", autocommits.len());
             for autocommit in autocommits {
-                info!("{}", autocommit);
+                let next_run = match autocommit.next_run(now) {
+                    _ if autocommit.paused => "paused".to_string(),
+                    Some(at) => format!("next run {}", at.format("%Y-%m-%d %H:%M")),
+                    None => "never runs".to_string(),
+                };
+                let problems = autocommit.problems();
+                if problems.is_empty() {
+                    info!(
+                        "{} [{}] {}",
+                        autocommit.repo_path(),
+                        autocommit.describe(),
+                        next_run
+                    );
+                } else {
+                    warn!(
+                        "{} [{}] {}: {}",
+                        autocommit.repo_path(),
+                        autocommit.frequency.join(" "),
+                        next_run,
+                        problems.join(", ")
+                    );
+                }
             }
         }
-        Commands::Delete { path } => {
-            let path = canonicalize(path)?;
-            info!("Deleting {}", path.display());
-
-            // Check if autocommit exists on path.
+        Commands::Status { path } => {
+            let path = path.as_ref().map(canonicalize).transpose()?;
+            let statuses = status(path.as_deref())?;
+            if cli.json {
+                println!("{}", serde_json::to_string(&statuses)?);
+                return Ok(());
+            }
+            for status in statuses {
+                info!("{}", status);
+            }
+        }
+        Commands::Delete { paths } => {
             let mut autocommits = list()?;
-            let mut deleted = false;
-            autocommits.retain(|e| {
-                // TODO: make this conditional better, and less error prone.
-                if e.args[1] != path.to_str().unwrap() {
-                    true
+            for path in paths {
+                let pattern = path.to_string_lossy();
+                // Repos that were moved or deleted can't be canonicalized, but
+                // their autocommit should still be removable.
+                let pattern = if pattern.contains('*') {
+                    pattern.to_string()
                 } else {
-                    deleted = true;
-                    false
+                    canonicalize(path)
+                        .map(|path| path.to_string_lossy().to_string())
+                        .unwrap_or_else(|_| pattern.to_string())
+                };
+
+                // Check if autocommit exists on path.
+                let before = autocommits.len();
+                autocommits.retain(|e| !config::glob_match(&pattern, e.repo_path()));
+                if autocommits.len() == before {
+                    return Err(eyre!("Autocommit not found on path {}", pattern));
                 }
-            });
-            if !deleted {
-                return Err(eyre!("Autocommit not found on path {}", path.display()));
+                info!(
+                    "Deleted {} autocommits on {}",
+                    before - autocommits.len(),
+                    pattern
+                );
             }
             debug!("Autocommits {:?}", autocommits);
             write_autocommits(&autocommits)?;
         }
-    }
-    Ok(())
-}
-
-#[derive(Debug, Default, Display)]
-#[display(fmt = "{:?} {:?} {:?}", frequency, command, args)]
-struct CronLine {
-    frequency: [String; 5],
-    command: String,
-    args: Vec<String>,
-}
-
-impl CronLine {
-    fn new(frequency: [String; 5], command: String, args: Vec<String>) -> Self {
-        Self {
-            frequency,
-            command,
-            args,
+        Commands::Message {
+            staged: _,
+            all,
+            path,
+        } => {
+            let path = canonicalize(path)?;
+            let source = if *all {
+                DiffSource::All
+            } else {
+                DiffSource::Staged
+            };
+            let commit_message = message(path, source).await?;
+            println!("{}", commit_message);
         }
-    }
-
-    fn parse(line: &str) -> Result<CronLine> {
-        let parts = line.split_whitespace();
-        let mut cron_line = CronLine::default();
-        for (i, part) in parts.enumerate() {
-            match i {
-                0..=4 => cron_line.frequency[i] = part.to_string(),
-                5 => cron_line.command = part.to_string(),
-                _ => cron_line.args.push(part.to_string()),
+        Commands::Hook { command } => match command {
+            HookCommands::Install { force, path } => {
+                let path = canonicalize(path)?;
+                info!("Installing prepare-commit-msg hook in {}", path.display());
+                install_hook(&path, *force)?;
+            }
+            HookCommands::Uninstall { path } => {
+                let path = canonicalize(path)?;
+                info!("Removing prepare-commit-msg hook from {}", path.display());
+                uninstall_hook(&path)?;
+            }
+        },
+        Commands::Auth { command } => match command {
+            AuthCommands::Set { key } => {
+                let key = match key {
+                    Some(key) => key.clone(),
+                    None => dialoguer::Password::new()
+                        .with_prompt("OpenAI API key")
+                        .interact()?,
+                };
+                auth::set(&key)?;
+                info!("Stored the OpenAI key in the keyring");
             }
+            AuthCommands::Unset => {
+                if auth::unset()? {
+                    info!("Removed the OpenAI key from the keyring");
+                } else {
+                    info!("No OpenAI key was stored in the keyring");
+                }
+            }
+        },
+        Commands::Secret { command } => match command {
+            SecretCommands::Encrypt { name } => {
+                let value = dialoguer::Password::new()
+                    .with_prompt(name.as_str())
+                    .interact()?;
+                println!("{} = \"{}\"", name, secrets::encrypt(&value)?);
+            }
+        },
+        Commands::Export => {
+            print!("{}", backup::export()?);
         }
-
-        if cron_line.command.is_empty() || cron_line.args.is_empty() {
-            return Err(eyre!("Invalid cron line, missing parts "));
+        Commands::Import { file } => {
+            let mut contents = String::new();
+            if file.as_os_str() == "-" {
+                std::io::stdin().read_to_string(&mut contents)?;
+            } else {
+                File::open(file)?.read_to_string(&mut contents)?;
+            }
+            backup::import(&contents)?;
         }
-
-        for part in cron_line.frequency.iter() {
-            if part.is_empty() {
-                return Err(eyre!("Invalid cron line frequency, missing parts "));
+        Commands::Doctor { path } => {
+            let path = path.as_ref().map(canonicalize).transpose()?;
+            doctor::doctor(path)?;
+        }
+        Commands::Completions { shell } => {
+            let mut command = Cli::command();
+            let name = command.get_name().to_string();
+            clap_complete::generate(*shell, &mut command, name, &mut std::io::stdout());
+            print!("{}", completions::repo_completion(*shell));
+        }
+        Commands::CompleteRepos => {
+            for autocommit in list()? {
+                println!("{}", autocommit.repo_path());
             }
         }
-
-        Ok(cron_line)
-    }
-
-    fn to_string(&self) -> String {
-        format!(
-            "{} {} {}",
-            self.frequency.join(" "),
-            self.command,
-            self.args.join(" ")
-        )
-    }
-}
-
-// TODO: this prevents the user from running other cron jobs rn :(
-fn write_autocommits(autocommits: &Vec<CronLine>) -> Result<()> {
-    let mut file = File::create("/tmp/crontab.txt")?;
-    let data = format!("OPENAI_API_KEY={}\n\n", env::var("OPENAI_API_KEY")?)
-        + &autocommits
-            .iter()
-            .map(|a| a.to_string())
-            .collect::<Vec<String>>()
-            .join("\n")
-        + "\n";
-    file.write_all(data.as_bytes())?;
-
-    // Create cron.
-    Command::new("crontab").arg("/tmp/crontab.txt").spawn()?;
-    Ok(())
-}
-
-fn list() -> Result<Vec<CronLine>> {
-    let command = Command::new("crontab")
-        .arg("-l")
-        .stdout(Stdio::piped())
-        .spawn()?;
-    let mut command_output = String::new();
-    command
-        .stdout
-        .unwrap()
-        .read_to_string(&mut command_output)?;
-    let lines = command_output.lines();
-    let mut autocommits = Vec::new();
-    for line in lines {
-        if line.contains(COMMAND_NAME) {
-            autocommits.push(CronLine::parse(line)?);
+        Commands::Ui => {
+            ui::run_dashboard()?;
         }
-    }
-    Ok(autocommits)
-}
-
-fn run_command_in_dir(dir: &std::path::PathBuf, command: &str, args: &[&str]) -> Result<String> {
-    let command = Command::new(command)
-        .args(args)
-        .current_dir(dir)
-        .stdout(Stdio::piped())
-        .spawn()?;
-    let mut command_output = String::new();
-    command
-        .stdout
-        .unwrap()
-        .read_to_string(&mut command_output)?;
-    Ok(command_output)
-}
-
-fn create(path: &std::path::PathBuf, frequency: u32) -> Result<()> {
-    let path = canonicalize(path)?;
-    if !path.join(".git").is_dir() {
-        return Err(eyre!("Path is not a git repo"));
-    }
-    info!(
-        "Creating autocommit on {} with frequency {}",
-        path.display(),
-        frequency
-    );
-    // Check if autocommit exists on path.
-    let mut autocommits = list()?;
-    for autocommit in autocommits.iter() {
-        // TODO: make this conditional better, and less error prone.
-        if autocommit.args[1] == path.to_str().unwrap() {
-            return Err(eyre!("Autocommit already exists on path"));
+        Commands::Logs {
+            path,
+            lines,
+            follow,
+        } => {
+            let path = canonicalize(path)?;
+            logs::show(&path, *lines, *follow)?;
         }
     }
-
-    // Now we're ready to create the autocommit.
-    // First add .autocommit_log to the .gitignore of the repo.
-    let mut gitignore = OpenOptions::new()
-        .create(true)
-        .write(true)
-        .append(true)
-        .open(path.join(".gitignore"))?;
-    gitignore.write_all(b"\n.autocommit_log\n")?;
-
-    let command_path = canonicalize(env::current_exe()?)?
-        .to_string_lossy()
-        .to_string();
-    debug!("Command path {}", command_path);
-
-    autocommits.push(CronLine::new(
-        [
-            format!("*/{}", frequency).to_string(),
-            "*".to_string(),
-            "*".to_string(),
-            "*".to_string(),
-            "*".to_string(),
-        ],
-        command_path,
-        vec![
-            "run".to_string(), // Run our binary.
-            path.to_str().unwrap().to_string(),
-            ">>".to_string(),
-            format!("{}/.autocommit_log", path.to_str().unwrap().to_string()),
-            "2>&1".to_string(),
-        ],
-    ));
-    write_autocommits(&autocommits)?;
     Ok(())
 }
-
-// Run command and helpers
-async fn run(repo_path: std::path::PathBuf) -> Result<()> {
-    // Check if the provided path is a git repo.
-    if !repo_path.join(".git").is_dir() {
-        return Err(eyre!("Path is not a git repo"));
-    }
-
-    // Run `git status` and check if there are any changes.
-    let git_status_out = run_command_in_dir(&repo_path, "git", &["status"])?;
-    if git_status_out.contains("nothing to commit, working tree clean") {
-        debug!("no changes: {}", git_status_out);
-        return Ok(());
-    }
-
-    // Run `git diff` to get the output changes.
-    let git_diff_out = run_command_in_dir(&repo_path, "git", &["diff"])?;
-    debug!("git diff output: {}", git_diff_out);
-
-    let api_key = env::var("OPENAI_API_KEY")?;
-    let commit_message = generate_commit_message(api_key, &git_diff_out).await?;
-    info!("commit message: {}", commit_message);
-
-    // Run `git commit -am {commit_message}` to add all changes.
-    run_command_in_dir(&repo_path, "git", &["commit", "-am", &commit_message])?;
-
-    // Run `git push` to push the changes.
-    run_command_in_dir(&repo_path, "git", &["push"])?;
-
-    Ok(())
-}
-
-async fn generate_commit_message(api_key: String, diff_string: &str) -> Result<String> {
-    // hehehe
-    let prompt = "You are CommitBot, an assistant tasked with writing helpful commit messages based on code changes.
-      You will be given a set of patches of code changes, and you must write a short commit message describing the changes. Do not be verbose. 
-      Your response must include only high level logical changes if the diff is large, otherwise you may include specific changes.
-      Try to fit your response in one line.
-      \n\n";
-
-    let client = Client::new(api_key);
-    // We want to use atmost 5 chunks of 1000 characters (arbitrary) to stay within the limit.
-
-    let mut total_commit_message = String::new();
-    for (index, chunk) in diff_string.as_bytes().chunks(5000).enumerate() {
-        if index > 5 {
-            break;
-        }
-        let req = ChatCompletionRequest {
-            model: chat_completion::GPT3_5_TURBO.to_string(),
-            messages: vec![chat_completion::ChatCompletionMessage {
-                role: chat_completion::MessageRole::user,
-                content: Some(format!("{}{}", prompt, String::from_utf8_lossy(chunk))),
-                name: None,
-                function_call: None,
-            }],
-            functions: None,
-            function_call: None,
-        };
-
-        let resp = client.chat_completion(req).await?;
-        let commit_message = resp.choices[0]
-            .message
-            .content
-            .clone()
-            .unwrap_or("Could not generate commit message".to_string())
-            .to_string();
-
-        total_commit_message.push_str(&commit_message);
-    }
-
-    Ok(total_commit_message)
-}
@@ -0,0 +1,670 @@
+use crate::config::{self, Profile};
+use crate::{auth, git_diff, ratelimit, DiffSource};
+use async_trait::async_trait;
+use color_eyre::{eyre::eyre, Result};
+use openai_api_rs::v1::chat_completion::{
+    self, ChatCompletionMessage, ChatCompletionRequest, ChatCompletionResponse, MessageRole,
+};
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tracing::{debug, info};
+
+/// Writes commit messages for diffs. Implement it to plug another model or
+/// service into [`crate::Autocommit`].
+#[async_trait]
+pub trait MessageGenerator: Send + Sync {
+    /// A commit message for `diff`, and how it was generated.
+    async fn generate(&self, diff: &str) -> Result<(String, Generation)>;
+
+    /// Like `generate`, for a repo holding the kind of content `profile`
+    /// describes. By default the message `generate` writes.
+    async fn generate_for(&self, diff: &str, _profile: Profile) -> Result<(String, Generation)> {
+        self.generate(diff).await
+    }
+
+    /// Sort the changed `files` into groups of related changes, one commit
+    /// each, for `--split`. By default everything goes into one commit.
+    async fn group(&self, files: &[String], _diff: &str) -> Result<Vec<Vec<String>>> {
+        Ok(vec![files.to_vec()])
+    }
+
+    /// A summary of a stretch of work from its commit `log` and combined
+    /// `diff`, for `run --since`. By default the message `generate` writes.
+    async fn summarize(&self, log: &str, diff: &str) -> Result<(String, Generation)> {
+        self.generate(&format!("Commits:\n{}\nDiff:\n{}", log, diff))
+            .await
+    }
+
+    /// A Markdown changelog of the commit subjects in `commits`, oldest
+    /// first, for `changelog`. By default a plain list of them.
+    async fn changelog(&self, commits: &[String]) -> Result<(String, Generation)> {
+        let list = commits
+            .iter()
+            .map(|commit| format!("- {}", commit))
+            .collect::<Vec<_>>();
+        Ok((
+            format!("## Changes\n\n{}", list.join("\n")),
+            Generation::default(),
+        ))
+    }
+
+    /// A Markdown pull request description for a branch of snapshots from
+    /// its commit `log` and diffstat `stat`, for `--open-pr`. By default the
+    /// summary `summarize` writes.
+    async fn describe_pull_request(&self, log: &str, stat: &str) -> Result<(String, Generation)> {
+        self.summarize(log, stat).await
+    }
+}
+
+/// Sends chat completion requests to OpenAI with the key from
+/// [`auth::openai_api_key`], reusing connections between requests.
+/// Connecting gives up after `CONNECT_TIMEOUT`, so being offline fails fast
+/// instead of waiting out a TCP timeout.
+pub struct OpenAiClient {
+    agent: ureq::Agent,
+}
+
+impl OpenAiClient {
+    pub fn new() -> Self {
+        let agent = ureq::AgentBuilder::new()
+            .timeout_connect(CONNECT_TIMEOUT)
+            .timeout(API_TIMEOUT)
+            .build();
+        Self { agent }
+    }
+
+    /// Ask [`MODEL`] to reply to `user` following the `system` prompt,
+    /// waiting for this request's turn under the rate limit first. The reply
+    /// is empty if the model returned no message.
+    pub(crate) async fn chat(&self, system: &str, user: &str) -> Result<(String, Generation)> {
+        let message = |role, content: &str| ChatCompletionMessage {
+            role,
+            content: Some(content.to_string()),
+            name: None,
+            function_call: None,
+        };
+        let req = ChatCompletionRequest {
+            model: MODEL.to_string(),
+            messages: vec![
+                message(MessageRole::system, system),
+                message(MessageRole::user, user),
+            ],
+            functions: None,
+            function_call: None,
+        };
+        let request = self
+            .agent
+            .post(&format!("{}/chat/completions", OPENAI_API))
+            .set(
+                "Authorization",
+                &format!("Bearer {}", auth::openai_api_key()?),
+            );
+
+        ratelimit::wait_for_turn().await?;
+        // ureq blocks, keep it off the runtime's worker threads.
+        let resp = tokio::task::spawn_blocking(move || -> Result<ChatCompletionResponse> {
+            Ok(request.send_json(req)?.into_json()?)
+        })
+        .await??;
+        let choice = resp
+            .choices
+            .first()
+            .ok_or_else(|| eyre!("OpenAI returned no choices"))?;
+        let generation = Generation {
+            model: Some(MODEL),
+            prompt_tokens: resp.usage.prompt_tokens as u64,
+            completion_tokens: resp.usage.completion_tokens as u64,
+            fallback: false,
+        };
+        Ok((
+            choice.message.content.clone().unwrap_or_default(),
+            generation,
+        ))
+    }
+}
+
+/// Whether `error` is a request that never reached OpenAI, e.g. because
+/// the machine is offline.
+fn unreachable(error: &color_eyre::Report) -> bool {
+    match error.downcast_ref::<ureq::Error>() {
+        Some(ureq::Error::Transport(transport)) => matches!(
+            transport.kind(),
+            ureq::ErrorKind::Dns | ureq::ErrorKind::ConnectionFailed
+        ),
+        _ => false,
+    }
+}
+
+/// How many characters of context go into requests that only need the gist.
+const MAX_CONTEXT_CHARS: usize = 8000;
+
+/// Cut `text` down to its first `max` characters.
+fn truncate_chars(text: &mut String, max: usize) {
+    if let Some((end, _)) = text.char_indices().nth(max) {
+        text.truncate(end);
+    }
+}
+
+impl Default for OpenAiClient {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Generates messages with OpenAI. Its client is shared by every repo the
+/// generator writes messages for.
+#[derive(Default)]
+pub struct OpenAiGenerator {
+    client: OpenAiClient,
+}
+
+impl OpenAiGenerator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl MessageGenerator for OpenAiGenerator {
+    async fn generate(&self, diff: &str) -> Result<(String, Generation)> {
+        self.generate_for(diff, Profile::Code).await
+    }
+
+    async fn generate_for(&self, diff: &str, profile: Profile) -> Result<(String, Generation)> {
+        let client = &self.client;
+        let result = match profile {
+            Profile::Code => generate_commit_message(client, diff).await,
+            Profile::Notes => generate_notes_message(client, diff).await,
+        };
+        match result {
+            Err(e) if unreachable(&e) => {
+                info!(
+                    "OpenAI is unreachable, committing with a placeholder message: {}",
+                    e
+                );
+                let generation = Generation {
+                    fallback: true,
+                    ..Default::default()
+                };
+                Ok((fallback_message(diff)?, generation))
+            }
+            result => result,
+        }
+    }
+
+    async fn group(&self, files: &[String], diff: &str) -> Result<Vec<Vec<String>>> {
+        match group_files(&self.client, files, diff).await {
+            Err(e) if unreachable(&e) => {
+                info!("OpenAI is unreachable, not splitting the changes: {}", e);
+                Ok(vec![files.to_vec()])
+            }
+            result => result,
+        }
+    }
+
+    async fn summarize(&self, log: &str, diff: &str) -> Result<(String, Generation)> {
+        summarize_changes(&self.client, log, diff).await
+    }
+
+    async fn changelog(&self, commits: &[String]) -> Result<(String, Generation)> {
+        write_changelog(&self.client, commits).await
+    }
+
+    async fn describe_pull_request(&self, log: &str, stat: &str) -> Result<(String, Generation)> {
+        describe_pull_request(&self.client, log, stat).await
+    }
+}
+
+/// Pipes the diff to a shell command and uses what it prints as the message,
+/// to plug in tools or services autocommit doesn't know about.
+#[derive(Debug)]
+pub struct ExecGenerator {
+    pub command: String,
+}
+
+#[async_trait]
+impl MessageGenerator for ExecGenerator {
+    async fn generate(&self, diff: &str) -> Result<(String, Generation)> {
+        debug!("Generating commit message with {}", self.command);
+        let mut child = tokio::process::Command::new("sh")
+            .arg("-c")
+            .arg(&self.command)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            // So the command doesn't outlive a run that timed out.
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|e| eyre!("Could not run generator {}: {}", self.command, e))?;
+        // Write concurrently so a command that prints before it has read all
+        // of the diff can't deadlock on a full pipe.
+        let mut stdin = child.stdin.take().unwrap();
+        let diff = diff.to_string();
+        let writer = tokio::spawn(async move { stdin.write_all(diff.as_bytes()).await });
+        let output = child.wait_with_output().await?;
+        // The command may exit without reading everything, which is fine.
+        let _ = writer.await;
+        if !output.status.success() {
+            return Err(eyre!(
+                "Generator {} exited with {}: {}",
+                self.command,
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+        let message = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        if message.is_empty() {
+            return Err(eyre!("Generator {} printed no message", self.command));
+        }
+        Ok((message, Generation::default()))
+    }
+}
+
+/// Commits with the [`fallback_message`], for when `[generator]` is disabled.
+#[derive(Debug)]
+pub struct TemplateGenerator;
+
+#[async_trait]
+impl MessageGenerator for TemplateGenerator {
+    async fn generate(&self, diff: &str) -> Result<(String, Generation)> {
+        Ok((fallback_message(diff)?, Generation::default()))
+    }
+}
+
+/// The generator configured under `[generator]`, OpenAI unless a command is
+/// set or generation is disabled.
+pub fn default_generator() -> Result<Box<dyn MessageGenerator>> {
+    let config = config::Config::load()?.generator;
+    Ok(match config.command {
+        _ if config.disabled => Box::new(TemplateGenerator),
+        Some(command) => Box::new(ExecGenerator { command }),
+        None => Box::new(OpenAiGenerator::new()),
+    })
+}
+
+/// Generate a commit message for the repo's changes without touching the repo.
+pub async fn message(repo_path: std::path::PathBuf, source: DiffSource) -> Result<String> {
+    if !repo_path.join(".git").is_dir() {
+        return Err(eyre!("Path is not a git repo"));
+    }
+
+    let git_diff_out = git_diff(&repo_path, source)?;
+    if git_diff_out.trim().is_empty() {
+        return Err(eyre!("No changes to generate a commit message for"));
+    }
+    debug!("git diff output: {}", git_diff_out);
+
+    let profile = config::Config::load()?.repo(&repo_path).profile;
+    let (commit_message, _) = default_generator()?
+        .generate_for(&git_diff_out, profile)
+        .await?;
+    Ok(commit_message)
+}
+
+/// The model used to generate commit messages.
+pub const MODEL: &str = chat_completion::GPT3_5_TURBO;
+const OPENAI_API: &str = "https://api.openai.com/v1";
+/// Being offline should fail fast instead of waiting out a TCP timeout.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(2);
+const API_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// A message written without a model, from `fallback_template` under
+/// `[generator]` if set, else naming the files changed in `diff`. The
+/// template can use `{files}` (e.g. `main.rs and 2 other files`),
+/// `{files_changed}`, `{insertions}`, `{deletions}`, `{date}` and `{time}`.
+/// Line counts come from the diff given to the model, so they stop growing
+/// once it is capped.
+pub fn fallback_message(diff: &str) -> Result<String> {
+    let template = config::Config::load()?.generator.fallback_template;
+    Ok(render_fallback(
+        template.as_deref(),
+        diff,
+        chrono::Local::now(),
+    ))
+}
+
+/// The [`fallback_message`] for `diff` from `template`, written at `now`.
+fn render_fallback(
+    template: Option<&str>,
+    diff: &str,
+    now: chrono::DateTime<chrono::Local>,
+) -> String {
+    let files = diff
+        .lines()
+        .filter_map(|line| line.strip_prefix("diff --git a/"))
+        .filter_map(|paths| paths.split(" b/").next())
+        .collect::<Vec<_>>();
+    let names = match files.as_slice() {
+        [] => "files".to_string(),
+        [file] => file.to_string(),
+        [first, second] => format!("{} and {}", first, second),
+        [first, rest @ ..] => format!("{} and {} other files", first, rest.len()),
+    };
+    let Some(template) = template else {
+        return format!("Update {}", names);
+    };
+    let insertions = diff
+        .lines()
+        .filter(|line| line.starts_with('+') && !line.starts_with("+++"))
+        .count();
+    let deletions = diff
+        .lines()
+        .filter(|line| line.starts_with('-') && !line.starts_with("---"))
+        .count();
+    template
+        .replace("{files_changed}", &files.len().to_string())
+        .replace("{files}", &names)
+        .replace("{insertions}", &insertions.to_string())
+        .replace("{deletions}", &deletions.to_string())
+        .replace("{date}", &now.format("%Y-%m-%d").to_string())
+        .replace("{time}", &now.format("%H:%M").to_string())
+}
+
+/// How a commit message was generated, recorded in the commit's git note.
+#[derive(Debug, Default, Clone, Copy, serde::Serialize)]
+pub struct Generation {
+    /// `None` if the message was not generated by a model, e.g. given with
+    /// `--message` or printed by an [`ExecGenerator`].
+    pub model: Option<&'static str>,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    /// The model returned no message for part of the diff and a placeholder
+    /// was used instead.
+    pub fallback: bool,
+}
+
+pub async fn generate_commit_message(
+    client: &OpenAiClient,
+    diff_string: &str,
+) -> Result<(String, Generation)> {
+    // hehehe
+    let prompt = "You are CommitBot, an assistant tasked with writing helpful commit messages based on code changes.
+      You will be given a set of patches of code changes, and you must write a short commit message describing the changes. Do not be verbose. 
+      Your response must include only high level logical changes if the diff is large, otherwise you may include specific changes.
+      Try to fit your response in one line.
+      \n\n";
+
+    // We want to use atmost 5 chunks of 1000 characters (arbitrary) to stay within the limit.
+
+    let mut total_commit_message = String::new();
+    let mut usage = Generation {
+        model: Some(MODEL),
+        ..Default::default()
+    };
+    for (index, chunk) in diff_string.as_bytes().chunks(5000).enumerate() {
+        if index > 5 {
+            break;
+        }
+        let (commit_message, generation) =
+            client.chat(prompt, &String::from_utf8_lossy(chunk)).await?;
+        usage.prompt_tokens += generation.prompt_tokens;
+        usage.completion_tokens += generation.completion_tokens;
+        if commit_message.is_empty() {
+            usage.fallback = true;
+        }
+
+        total_commit_message.push_str(&commit_message);
+    }
+    // One placeholder stands in for every chunk the model returned nothing for.
+    if usage.fallback {
+        total_commit_message.push_str(&fallback_message(diff_string)?);
+    }
+
+    Ok((total_commit_message, usage))
+}
+
+/// The notes `diff` adds, renames and deletes, and the Markdown headings it
+/// adds or removes, one per line, so the model sees the shape of the change
+/// before the prose.
+fn notes_outline(diff: &str) -> String {
+    let mut outline = Vec::new();
+    let mut file = "";
+    let mut whole_file = false;
+    for line in diff.lines() {
+        if let Some(paths) = line.strip_prefix("diff --git a/") {
+            file = paths.split(" b/").next().unwrap_or_default();
+            whole_file = false;
+        } else if line.starts_with("new file mode") {
+            whole_file = true;
+            outline.push(format!("New note: {}", note_title(file)));
+        } else if line.starts_with("deleted file mode") {
+            whole_file = true;
+            outline.push(format!("Deleted note: {}", note_title(file)));
+        } else if let Some(from) = line.strip_prefix("rename from ") {
+            outline.push(format!("Renamed note: {}", note_title(from)));
+        } else if let Some(to) = line.strip_prefix("rename to ") {
+            if let Some(last) = outline.last_mut() {
+                last.push_str(&format!(" -> {}", note_title(to)));
+            }
+        } else if !whole_file && (line.starts_with("+#") || line.starts_with("-#")) {
+            let (sign, heading) = line.split_at(1);
+            if heading.trim_start_matches('#').starts_with(' ') {
+                let change = if sign == "+" { "added" } else { "removed" };
+                outline.push(format!(
+                    "Heading {} in {}: {}",
+                    change,
+                    note_title(file),
+                    heading.trim_start_matches('#').trim()
+                ));
+            }
+        }
+    }
+    outline.join("\n")
+}
+
+/// A note's title, its file name without the `.md` extension.
+fn note_title(path: &str) -> &str {
+    let name = path.rsplit('/').next().unwrap_or(path);
+    name.strip_suffix(".md").unwrap_or(name)
+}
+
+/// Ask the model for a commit message for a Markdown notes vault, describing
+/// which notes changed and what about rather than code semantics.
+pub async fn generate_notes_message(
+    client: &OpenAiClient,
+    diff: &str,
+) -> Result<(String, Generation)> {
+    let prompt = "You are CommitBot, writing commit messages for a personal Markdown notes vault, such as an Obsidian vault.
+      You will be given an outline of the notes added, renamed and deleted and the headings changed, followed by the patches.
+      Write a short commit message saying which notes changed and what about, referring to notes by their title and mentioning new or renamed notes by name.
+      Describe the content, not the formatting, and do not use code conventions such as `feat:` or `fix:`.
+      Try to fit your response in one line.
+      \n\n";
+    // Keep the request small, the outline carries most of what matters.
+    let mut context = format!("Outline:\n{}\n\nPatches:\n{}", notes_outline(diff), diff);
+    truncate_chars(&mut context, MAX_CONTEXT_CHARS);
+
+    let (message, mut usage) = client.chat(prompt, &context).await?;
+    if message.is_empty() {
+        usage.fallback = true;
+        return Ok((fallback_message(diff)?, usage));
+    }
+    Ok((message, usage))
+}
+
+/// Ask the model for a standup-style summary of the commits in `log` and
+/// their combined `diff`.
+pub async fn summarize_changes(
+    client: &OpenAiClient,
+    log: &str,
+    diff: &str,
+) -> Result<(String, Generation)> {
+    let prompt = "You are CommitBot. Below are the commit messages and the combined diff of a stretch of work, much of it automatic work-in-progress snapshots.
+      Summarize what was done as a short bullet list of the main changes, suitable for a standup update or a pull request description.
+      Describe the overall changes, not the individual commits.
+      \n\n";
+    // Keep the request small, the summary only needs the gist.
+    let mut context = format!("Commits:\n{}\nDiff:\n{}", log, diff);
+    truncate_chars(&mut context, MAX_CONTEXT_CHARS);
+
+    let (summary, mut usage) = client.chat(prompt, &context).await?;
+    if summary.is_empty() {
+        usage.fallback = true;
+        return Ok(("Could not generate summary".to_string(), usage));
+    }
+    Ok((summary, usage))
+}
+
+/// Ask the model to turn commit subjects into a changelog grouped by kind of
+/// change.
+pub async fn write_changelog(
+    client: &OpenAiClient,
+    commits: &[String],
+) -> Result<(String, Generation)> {
+    let prompt = "You are CommitBot. Below are the subjects of a project's commits, oldest first, many of them automatic work-in-progress snapshots.
+      Write a changelog in Markdown with `### Added`, `### Changed`, `### Fixed` and `### Removed` sections, leaving out empty ones.
+      Use one bullet per user-visible change, merging commits that are part of the same change and leaving out ones that don't matter to users.
+      Reply with only the changelog.
+      \n\n";
+    // Keep the request small, subjects carry enough for a changelog.
+    let mut context = commits.join("\n");
+    truncate_chars(&mut context, MAX_CONTEXT_CHARS);
+
+    let (changelog, mut usage) = client.chat(prompt, &context).await?;
+    if changelog.is_empty() {
+        usage.fallback = true;
+        return Ok(("Could not generate changelog".to_string(), usage));
+    }
+    Ok((changelog, usage))
+}
+
+/// Ask the model for a pull request description of a branch of snapshots
+/// from its commit `log` and diffstat `stat`.
+pub async fn describe_pull_request(
+    client: &OpenAiClient,
+    log: &str,
+    stat: &str,
+) -> Result<(String, Generation)> {
+    let prompt = "You are CommitBot. Below are the commit messages and diffstat of a branch of automatic work-in-progress snapshots.
+      Write a short pull request description in markdown: a one paragraph summary of the overall change followed by a bullet list of the main changes.
+      Do not mention the individual snapshots.
+      \n\n";
+    // Keep the request small, the summary only needs the gist.
+    let mut context = format!("Commits:\n{}\nDiffstat:\n{}", log, stat);
+    truncate_chars(&mut context, MAX_CONTEXT_CHARS);
+
+    let (description, mut usage) = client.chat(prompt, &context).await?;
+    if description.is_empty() {
+        usage.fallback = true;
+        return Ok(("Could not generate description".to_string(), usage));
+    }
+    Ok((description, usage))
+}
+
+/// Ask the model to group `files` into logical commits. Falls back to a
+/// single group if the reply isn't the JSON we asked for.
+pub async fn group_files(
+    client: &OpenAiClient,
+    files: &[String],
+    diff: &str,
+) -> Result<Vec<Vec<String>>> {
+    let prompt = "You are CommitBot, an assistant that splits code changes into logical commits.
+      Group the changed files below so that each group is one self-contained change, keeping related files together.
+      Reply with only a JSON array of arrays of file paths, using every file exactly once.";
+    let context = format!("Files:\n{}\n\nDiff:\n{}", files.join("\n"), diff);
+    let (reply, _) = client.chat(prompt, &context).await?;
+    match serde_json::from_str(reply.trim()) {
+        Ok(groups) => Ok(groups),
+        Err(e) => {
+            debug!("Could not parse file groups {:?}: {}", reply, e);
+            Ok(vec![files.to_vec()])
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn diff_of(files: &[&str]) -> String {
+        files
+            .iter()
+            .map(|file| format!("diff --git a/{0} b/{0}\n--- a/{0}\n+++ b/{0}\n", file))
+            .collect()
+    }
+
+    #[test]
+    fn fallback_names_changed_files() {
+        let now = chrono::Local::now();
+        assert_eq!(render_fallback(None, "", now), "Update files");
+        assert_eq!(
+            render_fallback(None, &diff_of(&["a.rs"]), now),
+            "Update a.rs"
+        );
+        assert_eq!(
+            render_fallback(None, &diff_of(&["a.rs", "b.rs"]), now),
+            "Update a.rs and b.rs"
+        );
+        assert_eq!(
+            render_fallback(None, &diff_of(&["a.rs", "b.rs", "c.rs"]), now),
+            "Update a.rs and 2 other files"
+        );
+    }
+
+    #[test]
+    fn fallback_fills_template() {
+        let now = chrono::Local
+            .with_ymd_and_hms(2024, 3, 9, 14, 5, 0)
+            .unwrap();
+        let diff = format!(
+            "{}@@ -1 +1,2 @@\n-old\n+new\n+more\n",
+            diff_of(&["a.rs", "b.rs"])
+        );
+        assert_eq!(
+            render_fallback(
+                Some("wip: {files} ({files_changed}, +{insertions} -{deletions}) {date} {time}"),
+                &diff,
+                now
+            ),
+            "wip: a.rs and b.rs (2, +2 -1) 2024-03-09 14:05"
+        );
+    }
+
+    #[test]
+    fn notes_outline_lists_notes_and_headings() {
+        let diff = "\
+diff --git a/Ideas.md b/Ideas.md
+new file mode 100644
+--- /dev/null
++++ b/Ideas.md
+@@ -0,0 +1 @@
++# Ideas
+diff --git a/journal/Today.md b/journal/Today.md
+--- a/journal/Today.md
++++ b/journal/Today.md
+@@ -1,2 +1,2 @@
+-### Old
++## Plans
++#tag
+diff --git a/Old.md b/New.md
+similarity index 100%
+rename from Old.md
+rename to New.md
+diff --git a/Gone.md b/Gone.md
+deleted file mode 100644
+--- a/Gone.md
++++ /dev/null
+@@ -1 +0,0 @@
+-# Gone
+";
+        assert_eq!(
+            notes_outline(diff),
+            "New note: Ideas\n\
+             Heading removed in Today: Old\n\
+             Heading added in Today: Plans\n\
+             Renamed note: Old -> New\n\
+             Deleted note: Gone"
+        );
+    }
+
+    #[test]
+    fn truncate_chars_keeps_whole_characters() {
+        let mut text = "héllo wörld".to_string();
+        truncate_chars(&mut text, 7);
+        assert_eq!(text, "héllo w");
+        truncate_chars(&mut text, 100);
+        assert_eq!(text, "héllo w");
+    }
+}
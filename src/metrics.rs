@@ -0,0 +1,115 @@
+use crate::config::MetricsConfig;
+use crate::state::RepoState;
+use crate::{failure_message, RunOutcome, COMMAND_NAME};
+use base64::Engine;
+use color_eyre::Result;
+use std::fmt::Write;
+use std::time::Duration;
+use tracing::warn;
+
+const PUSHGATEWAY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Push the repo's metrics to a Prometheus Pushgateway, grouped by repo so
+/// every repo keeps its own series. Counters come from the state file because
+/// each push replaces the whole group. Failing to push is logged but never
+/// fails the run.
+pub fn push_metrics(
+    config: &MetricsConfig,
+    repo_path: &std::path::Path,
+    repo_state: &RepoState,
+    result: &Result<RunOutcome>,
+) {
+    if let Err(e) = try_push_metrics(config, repo_path, repo_state, result) {
+        warn!("Could not push metrics to {}: {}", config.pushgateway, e);
+    }
+}
+
+fn try_push_metrics(
+    config: &MetricsConfig,
+    repo_path: &std::path::Path,
+    repo_state: &RepoState,
+    result: &Result<RunOutcome>,
+) -> Result<()> {
+    let repo = repo_path.to_string_lossy();
+    let url = format!(
+        "{}/metrics/job/{}/repo@base64/{}",
+        config.pushgateway.trim_end_matches('/'),
+        COMMAND_NAME,
+        base64::engine::general_purpose::URL_SAFE.encode(repo.as_bytes())
+    );
+    ureq::put(&url)
+        .timeout(PUSHGATEWAY_TIMEOUT)
+        .set("Content-Type", "text/plain; version=0.0.4")
+        .send_string(&render(repo_state, result))?;
+    Ok(())
+}
+
+/// Render the metrics in the Prometheus text exposition format.
+fn render(repo_state: &RepoState, result: &Result<RunOutcome>) -> String {
+    let mut out = String::new();
+    let mut metric = |name: &str, kind: &str, help: &str, value: f64| {
+        let _ = writeln!(out, "# HELP {} {}", name, help);
+        let _ = writeln!(out, "# TYPE {} {}", name, kind);
+        let _ = writeln!(out, "{} {}", name, value);
+    };
+
+    metric(
+        "autocommit_runs_total",
+        "counter",
+        "Runs of autocommit.",
+        repo_state.runs as f64,
+    );
+    metric(
+        "autocommit_commits_total",
+        "counter",
+        "Commits made by autocommit.",
+        repo_state.commits as f64,
+    );
+    metric(
+        "autocommit_failures_total",
+        "counter",
+        "Runs that failed.",
+        repo_state.failures as f64,
+    );
+    metric(
+        "autocommit_prompt_tokens_total",
+        "counter",
+        "Prompt tokens sent to the LLM.",
+        repo_state.prompt_tokens as f64,
+    );
+    metric(
+        "autocommit_completion_tokens_total",
+        "counter",
+        "Completion tokens received from the LLM.",
+        repo_state.completion_tokens as f64,
+    );
+    metric(
+        "autocommit_last_run_success",
+        "gauge",
+        "Whether the last run succeeded.",
+        if failure_message(result).is_none() {
+            1.0
+        } else {
+            0.0
+        },
+    );
+    metric(
+        "autocommit_last_run_timestamp_seconds",
+        "gauge",
+        "When the last run finished.",
+        chrono::Utc::now().timestamp() as f64,
+    );
+    if let Ok(RunOutcome::Committed {
+        push_duration: Some(push_duration),
+        ..
+    }) = result
+    {
+        metric(
+            "autocommit_push_duration_seconds",
+            "gauge",
+            "How long the last push took.",
+            push_duration.as_secs_f64(),
+        );
+    }
+    out
+}
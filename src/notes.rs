@@ -0,0 +1,33 @@
+use crate::{run_checked_command_in_dir, Generation};
+use color_eyre::Result;
+use serde::{Deserialize, Serialize};
+
+/// Notes are kept under `refs/notes/autocommit` so they don't mix with the
+/// user's own notes.
+pub const NOTES_REF: &str = "autocommit";
+
+/// The generation metadata recorded for an autocommit, as read back from its
+/// note.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Note {
+    pub model: Option<String>,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    pub fallback: bool,
+}
+
+/// Attach how `sha`'s message was generated to it as a JSON git note.
+pub fn add(repo_path: &std::path::PathBuf, sha: &str, generation: &Generation) -> Result<()> {
+    let note = serde_json::to_string(generation)?;
+    run_checked_command_in_dir(
+        repo_path,
+        "git",
+        &["notes", "--ref", NOTES_REF, "add", "-f", "-m", &note, sha],
+    )?;
+    Ok(())
+}
+
+/// Parse a note written by [`add`], `None` if it is missing or not ours.
+pub fn parse(note: &str) -> Option<Note> {
+    serde_json::from_str(note.trim()).ok()
+}
@@ -0,0 +1,215 @@
+use crate::config::{Config, EmailConfig};
+use crate::{failure_message, RepoOptions, RunOutcome, COMMAND_NAME};
+use color_eyre::Result;
+use derive_more::Display;
+use serde_json::json;
+use std::time::Duration;
+use tracing::warn;
+
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+const HEALTHCHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Which run results produce a notification.
+#[derive(clap::ValueEnum, Debug, Default, Display, Clone, Copy, PartialEq, Eq)]
+pub enum NotifyOn {
+    #[default]
+    #[display(fmt = "never")]
+    Never,
+    #[display(fmt = "failure")]
+    Failure,
+    #[display(fmt = "always")]
+    Always,
+}
+
+impl NotifyOn {
+    fn wants(self, notification: &Notification) -> bool {
+        match self {
+            NotifyOn::Never => false,
+            NotifyOn::Failure => notification.failed,
+            NotifyOn::Always => true,
+        }
+    }
+}
+
+/// A finished run worth telling someone about.
+struct Notification {
+    failed: bool,
+    summary: String,
+    body: String,
+    /// Structured details for sinks that accept JSON.
+    details: serde_json::Value,
+}
+
+impl Notification {
+    /// Runs that did not commit anything are not worth a notification.
+    fn from_result(repo_path: &std::path::Path, result: &Result<RunOutcome>) -> Option<Self> {
+        let repo = repo_path
+            .file_name()
+            .unwrap_or(repo_path.as_os_str())
+            .to_string_lossy();
+        match result {
+            Ok(RunOutcome::Committed {
+                sha,
+                branch,
+                message,
+                files,
+                pushed,
+                push_error,
+                ..
+            }) => Some(Notification {
+                failed: push_error.is_some(),
+                summary: match push_error {
+                    Some(_) => format!("Autocommitted {} ({}), but pushing failed", repo, branch),
+                    None => format!("Autocommitted {} ({})", repo, branch),
+                },
+                body: match push_error {
+                    Some(error) => format!("{}\n{} files changed\n{}", message, files.len(), error),
+                    None => format!("{}\n{} files changed", message, files.len()),
+                },
+                details: json!({
+                    "repo": repo_path,
+                    "branch": branch,
+                    "sha": sha,
+                    "message": message,
+                    "files_changed": files,
+                    "pushed": pushed,
+                    "push_error": push_error,
+                }),
+            }),
+            Ok(_) => None,
+            Err(e) => Some(Notification {
+                failed: true,
+                summary: format!("Autocommit failed in {}", repo),
+                body: format!("{:#}", e),
+                details: json!({
+                    "repo": repo_path,
+                    "error": format!("{:#}", e),
+                }),
+            }),
+        }
+    }
+}
+
+/// Send a finished run to every notification sink configured in `options`.
+/// Failing to notify is logged but never fails the run.
+pub fn notify_run(repo_path: &std::path::Path, options: &RepoOptions, result: &Result<RunOutcome>) {
+    let Some(notification) = Notification::from_result(repo_path, result) else {
+        return;
+    };
+
+    if options.notify.wants(&notification) {
+        if let Err(e) = notify_desktop(&notification) {
+            warn!("Could not show desktop notification: {}", e);
+        }
+    }
+    if let Some(url) = &options.webhook {
+        if options.webhook_on.wants(&notification) {
+            if let Err(e) = notify_webhook(url, &notification) {
+                warn!("Could not post to webhook: {}", e);
+            }
+        }
+    }
+}
+
+fn notify_desktop(notification: &Notification) -> Result<()> {
+    notify_rust::Notification::new()
+        .appname(COMMAND_NAME)
+        .summary(&notification.summary)
+        .body(&notification.body)
+        .show()?;
+    Ok(())
+}
+
+/// Post the notification as JSON. `text` is what Slack displays and `content`
+/// what Discord displays; other endpoints can use the structured fields.
+fn notify_webhook(url: &str, notification: &Notification) -> Result<()> {
+    let text = format!("*{}*\n{}", notification.summary, notification.body);
+    let mut payload = json!({
+        "text": text,
+        "content": text,
+    });
+    if let (Some(payload), Some(details)) =
+        (payload.as_object_mut(), notification.details.as_object())
+    {
+        payload.extend(details.clone());
+    }
+    ureq::post(url)
+        .timeout(WEBHOOK_TIMEOUT)
+        .send_json(payload)?;
+    Ok(())
+}
+
+/// The run events a healthcheck URL is pinged for.
+pub enum Ping {
+    Start,
+    Success,
+    /// Carries the error, which healthchecks.io shows as the ping body.
+    Fail(String),
+}
+
+/// Ping a healthchecks.io-style dead man's switch: `<url>/start` when a run
+/// starts, `<url>` when it succeeds and `<url>/fail` when it fails. Failing to
+/// ping is logged but never fails the run.
+pub fn ping_healthcheck(url: &str, ping: Ping) {
+    let url = url.trim_end_matches('/');
+    let result = match ping {
+        Ping::Start => ureq::get(&format!("{}/start", url))
+            .timeout(HEALTHCHECK_TIMEOUT)
+            .call(),
+        Ping::Success => ureq::get(url).timeout(HEALTHCHECK_TIMEOUT).call(),
+        Ping::Fail(error) => ureq::post(&format!("{}/fail", url))
+            .timeout(HEALTHCHECK_TIMEOUT)
+            .send_string(&error),
+    };
+    if let Err(e) = result {
+        warn!("Could not ping healthcheck {}: {}", url, e);
+    }
+}
+
+/// Email the configured address when a repo's failure streak reaches the
+/// configured threshold. Only the run that reaches it sends mail, so a broken
+/// repo does not send one email per scheduled run.
+pub fn alert_repeated_failures(
+    repo_path: &std::path::Path,
+    config: &Config,
+    consecutive_failures: u32,
+    result: &Result<RunOutcome>,
+) {
+    let (Some(email), Some(error)) = (&config.email, failure_message(result)) else {
+        return;
+    };
+    if consecutive_failures != email.after_failures {
+        return;
+    }
+    let subject = format!(
+        "Autocommit failed {} times in a row in {}",
+        consecutive_failures,
+        repo_path.display()
+    );
+    let body = format!(
+        "Autocommit has failed the last {} runs on {}, so changes are no longer being committed or pushed.\n\nLast error:\n{}\n",
+        consecutive_failures,
+        repo_path.display(),
+        error
+    );
+    if let Err(e) = send_email(email, &subject, &body) {
+        warn!("Could not send failure alert email: {}", e);
+    }
+}
+
+fn send_email(email: &EmailConfig, subject: &str, body: &str) -> Result<()> {
+    use lettre::transport::smtp::authentication::Credentials;
+    use lettre::{Message, SmtpTransport, Transport};
+
+    let message = Message::builder()
+        .from(email.from.parse()?)
+        .to(email.to.parse()?)
+        .subject(subject)
+        .body(body.to_string())?;
+    let mut mailer = SmtpTransport::relay(&email.smtp_host)?.port(email.smtp_port);
+    if let (Some(username), Some(password)) = (&email.username, &email.password) {
+        mailer = mailer.credentials(Credentials::new(username.clone(), password.clone()));
+    }
+    mailer.build().send(&message)?;
+    Ok(())
+}
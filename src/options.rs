@@ -0,0 +1,218 @@
+use crate::notify;
+use clap::Args;
+use derive_more::Display;
+
+/// Per-repo options shared by `run` and `create`. `create` stores them in the
+/// scheduled `run` invocation.
+#[derive(Args, Debug, Default, Clone)]
+pub struct RepoOptions {
+    /// When to push: `always`, `never`, `commits:N` (once N commits are
+    /// unpushed) or `minutes:N` (once the oldest unpushed commit is N minutes old).
+    #[clap(long, default_value = "always")]
+    pub push: PushPolicy,
+
+    /// Never push automatically, same as `--push never`.
+    #[clap(long, conflicts_with = "push")]
+    pub no_push: bool,
+
+    /// When to show a desktop notification about the run.
+    #[clap(long, value_enum, default_value_t)]
+    pub notify: notify::NotifyOn,
+
+    /// Webhook URL (Slack, Discord or any JSON endpoint) to post run results to.
+    #[clap(long)]
+    pub webhook: Option<String>,
+
+    /// Which run results are posted to the webhook.
+    #[clap(long, value_enum, default_value = "always")]
+    pub webhook_on: notify::NotifyOn,
+
+    /// Healthchecks.io-style URL to ping when a run starts, succeeds (the URL
+    /// itself) or fails (`/fail`).
+    #[clap(long)]
+    pub healthcheck: Option<String>,
+
+    /// Amend the previous autocommit instead of adding a new commit if it was
+    /// made less than this many minutes ago and has not been pushed.
+    #[clap(long, value_name = "MINUTES")]
+    pub amend_within: Option<u32>,
+
+    /// Commit snapshots to `autocommit/<branch>` instead of the checked-out
+    /// branch, leaving its history untouched.
+    #[clap(long)]
+    pub snapshot_branch: bool,
+
+    /// With --snapshot-branch, open (or update) a draft GitHub pull request or
+    /// GitLab merge request from the snapshot branch after each push. Needs
+    /// GITHUB_TOKEN or GITLAB_TOKEN.
+    #[clap(long, requires = "snapshot_branch")]
+    pub open_pr: bool,
+
+    /// Commit to branches listed as protected in the config (by default
+    /// `main`, `master` and `release/*`).
+    #[clap(long)]
+    pub allow_protected: bool,
+
+    /// Squash each day's autocommits into one commit on the first run of the
+    /// next day.
+    #[clap(long)]
+    pub squash_daily: bool,
+
+    /// Tag the last commit of each day as `autocommit/<YYYY-MM-DD>` on the
+    /// first run of the next day, pushing the tag along with the commits.
+    #[clap(long)]
+    pub tag_daily: bool,
+
+    /// Allow rewriting autocommits that were already pushed, and force push
+    /// them with `--force-with-lease`. Applies to `--squash-daily`, and to
+    /// `--amend-within` when combined with `--push always`.
+    #[clap(long)]
+    pub force_with_lease: bool,
+
+    /// Skip the repo's own `pre-commit` and `commit-msg` hooks, which
+    /// autocommits run like any other commit by default.
+    #[clap(long)]
+    pub no_verify: bool,
+
+    /// Only check, diff and commit changes matching this pathspec, e.g.
+    /// `services/api`, which keeps idle runs fast in large monorepos. Can be
+    /// repeated.
+    #[clap(long = "pathspec", value_name = "PATHSPEC")]
+    pub pathspecs: Vec<String>,
+    /// Only commit what is already staged, leaving unstaged changes and
+    /// untracked files out of the autocommit.
+    #[clap(long, conflicts_with_all = ["pathspecs", "snapshot_branch"])]
+    pub staged_only: bool,
+    /// Ask the model to group unrelated changes into separate commits, each
+    /// with its own message, instead of one mixed commit.
+    #[clap(long, conflicts_with_all = ["staged_only", "snapshot_branch"])]
+    pub split: bool,
+    /// Commit the changes under this directory separately from the rest, with
+    /// a message generated from just their diff. Can be repeated, e.g.
+    /// `--group docs --group src`.
+    #[clap(
+        long = "group",
+        value_name = "DIR",
+        conflicts_with_all = ["split", "staged_only", "snapshot_branch"]
+    )]
+    pub groups: Vec<String>,
+    /// Append `.gitignore` entries for untracked junk files (`.DS_Store`,
+    /// swap files, `__pycache__`, ...) instead of only suggesting them.
+    #[clap(long)]
+    pub ignore_junk: bool,
+}
+
+impl RepoOptions {
+    pub fn push_policy(&self) -> PushPolicy {
+        if self.no_push {
+            PushPolicy::Never
+        } else {
+            self.push
+        }
+    }
+
+    /// Command line arguments that reproduce these options for `run`.
+    pub fn to_args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        let push = self.push_policy();
+        if push != PushPolicy::Always {
+            args.push("--push".to_string());
+            args.push(push.to_string());
+        }
+        if self.notify != notify::NotifyOn::Never {
+            args.push("--notify".to_string());
+            args.push(self.notify.to_string());
+        }
+        if let Some(webhook) = &self.webhook {
+            args.push("--webhook".to_string());
+            args.push(webhook.clone());
+            if self.webhook_on != notify::NotifyOn::Always {
+                args.push("--webhook-on".to_string());
+                args.push(self.webhook_on.to_string());
+            }
+        }
+        if let Some(healthcheck) = &self.healthcheck {
+            args.push("--healthcheck".to_string());
+            args.push(healthcheck.clone());
+        }
+        if let Some(minutes) = self.amend_within {
+            args.push("--amend-within".to_string());
+            args.push(minutes.to_string());
+        }
+        if self.snapshot_branch {
+            args.push("--snapshot-branch".to_string());
+        }
+        if self.open_pr {
+            args.push("--open-pr".to_string());
+        }
+        if self.allow_protected {
+            args.push("--allow-protected".to_string());
+        }
+        if self.squash_daily {
+            args.push("--squash-daily".to_string());
+        }
+        if self.tag_daily {
+            args.push("--tag-daily".to_string());
+        }
+        if self.force_with_lease {
+            args.push("--force-with-lease".to_string());
+        }
+        if self.no_verify {
+            args.push("--no-verify".to_string());
+        }
+        if self.staged_only {
+            args.push("--staged-only".to_string());
+        }
+        if self.split {
+            args.push("--split".to_string());
+        }
+        for group in &self.groups {
+            args.push("--group".to_string());
+            args.push(group.clone());
+        }
+        if self.ignore_junk {
+            args.push("--ignore-junk".to_string());
+        }
+        for pathspec in &self.pathspecs {
+            args.push("--pathspec".to_string());
+            args.push(pathspec.clone());
+        }
+        args
+    }
+}
+
+#[derive(Debug, Default, Display, Clone, Copy, PartialEq, Eq)]
+pub enum PushPolicy {
+    #[default]
+    #[display(fmt = "always")]
+    Always,
+    #[display(fmt = "never")]
+    Never,
+    #[display(fmt = "commits:{}", _0)]
+    Commits(u32),
+    #[display(fmt = "minutes:{}", _0)]
+    Minutes(u32),
+}
+
+impl std::str::FromStr for PushPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parse_count = |n: &str| {
+            n.parse::<u32>()
+                .ok()
+                .filter(|n| *n > 0)
+                .ok_or_else(|| format!("Invalid count in push policy: {}", s))
+        };
+        match s.split_once(':') {
+            None if s == "always" => Ok(PushPolicy::Always),
+            None if s == "never" => Ok(PushPolicy::Never),
+            Some(("commits", n)) => Ok(PushPolicy::Commits(parse_count(n)?)),
+            Some(("minutes", n)) => Ok(PushPolicy::Minutes(parse_count(n)?)),
+            _ => Err(format!(
+                "Invalid push policy {}, expected always, never, commits:N or minutes:N",
+                s
+            )),
+        }
+    }
+}
@@ -0,0 +1,181 @@
+use crate::{run_checked_command_in_dir, Generation, MessageGenerator};
+use color_eyre::{eyre::eyre, Result};
+use serde_json::{json, Value};
+use std::env;
+use std::time::Duration;
+use tracing::info;
+
+const API_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Where a remote is hosted, parsed from its URL.
+enum Forge {
+    GitHub { owner_repo: String },
+    GitLab { host: String, project: String },
+}
+
+impl Forge {
+    /// Understands `git@host:owner/repo.git`, `ssh://git@host/owner/repo.git`
+    /// and `https://host/owner/repo.git`. Any host with "gitlab" in its name is
+    /// treated as GitLab.
+    fn from_url(url: &str) -> Option<Self> {
+        let url = url.trim().trim_end_matches('/').trim_end_matches(".git");
+        let (host, path) = match url.split_once("://") {
+            Some((_, rest)) => rest.split_once('/')?,
+            None => url.split_once(':')?,
+        };
+        let host = host.rsplit('@').next()?.split(':').next()?.to_string();
+        let path = path.trim_start_matches('/').to_string();
+        if host == "github.com" {
+            Some(Forge::GitHub { owner_repo: path })
+        } else if host.contains("gitlab") {
+            Some(Forge::GitLab {
+                host,
+                project: path,
+            })
+        } else {
+            None
+        }
+    }
+}
+
+/// Open a draft pull/merge request from `head` into `base` on `remote`, or
+/// refresh the description of the one that is already open. `generator`
+/// writes the description.
+pub async fn open_or_update(
+    repo_path: &std::path::PathBuf,
+    remote: &str,
+    head: &str,
+    base: &str,
+    generator: &dyn MessageGenerator,
+) -> Result<Generation> {
+    let url = run_checked_command_in_dir(repo_path, "git", &["remote", "get-url", remote])?;
+    let forge = Forge::from_url(&url).ok_or_else(|| {
+        eyre!(
+            "Cannot open a pull request for {}, only GitHub and GitLab are supported",
+            url.trim()
+        )
+    })?;
+
+    let log = run_checked_command_in_dir(
+        repo_path,
+        "git",
+        &[
+            "log",
+            "--no-merges",
+            "--format=- %s",
+            &format!("{}..{}", base, head),
+        ],
+    )?;
+    let stat = run_checked_command_in_dir(
+        repo_path,
+        "git",
+        &["diff", "--stat", &format!("{}...{}", base, head)],
+    )?;
+    let (description, usage) = generator.describe_pull_request(&log, &stat).await?;
+    let title = format!("autocommit: snapshots of {}", base);
+
+    // ureq blocks, keep it off the runtime's worker threads.
+    let (head, base) = (head.to_string(), base.to_string());
+    tokio::task::spawn_blocking(move || match forge {
+        Forge::GitHub { owner_repo } => github(&owner_repo, &head, &base, &title, &description),
+        Forge::GitLab { host, project } => {
+            gitlab(&host, &project, &head, &base, &title, &description)
+        }
+    })
+    .await??;
+    Ok(usage)
+}
+
+fn github(owner_repo: &str, head: &str, base: &str, title: &str, body: &str) -> Result<()> {
+    let token = env::var("GITHUB_TOKEN").map_err(|_| eyre!("GITHUB_TOKEN is not set"))?;
+    let api = format!("https://api.github.com/repos/{}/pulls", owner_repo);
+    let auth = format!("Bearer {}", token);
+    let owner = owner_repo.split('/').next().unwrap_or_default();
+
+    let open: Vec<Value> = ureq::get(&api)
+        .query("head", &format!("{}:{}", owner, head))
+        .query("base", base)
+        .query("state", "open")
+        .set("Authorization", &auth)
+        .timeout(API_TIMEOUT)
+        .call()?
+        .into_json()?;
+    match open.first().and_then(|pr| pr["number"].as_u64()) {
+        Some(number) => {
+            ureq::patch(&format!("{}/{}", api, number))
+                .set("Authorization", &auth)
+                .timeout(API_TIMEOUT)
+                .send_json(json!({ "body": body }))?;
+            info!("Updated pull request #{}", number);
+        }
+        None => {
+            let pr: Value = ureq::post(&api)
+                .set("Authorization", &auth)
+                .timeout(API_TIMEOUT)
+                .send_json(json!({
+                    "title": title,
+                    "head": head,
+                    "base": base,
+                    "body": body,
+                    "draft": true,
+                }))?
+                .into_json()?;
+            info!(
+                "Opened pull request {}",
+                pr["html_url"].as_str().unwrap_or_default()
+            );
+        }
+    }
+    Ok(())
+}
+
+fn gitlab(
+    host: &str,
+    project: &str,
+    head: &str,
+    base: &str,
+    title: &str,
+    description: &str,
+) -> Result<()> {
+    let token = env::var("GITLAB_TOKEN").map_err(|_| eyre!("GITLAB_TOKEN is not set"))?;
+    let api = format!(
+        "https://{}/api/v4/projects/{}/merge_requests",
+        host,
+        project.replace('/', "%2F")
+    );
+
+    let open: Vec<Value> = ureq::get(&api)
+        .query("source_branch", head)
+        .query("target_branch", base)
+        .query("state", "opened")
+        .set("PRIVATE-TOKEN", &token)
+        .timeout(API_TIMEOUT)
+        .call()?
+        .into_json()?;
+    match open.first().and_then(|mr| mr["iid"].as_u64()) {
+        Some(iid) => {
+            ureq::put(&format!("{}/{}", api, iid))
+                .set("PRIVATE-TOKEN", &token)
+                .timeout(API_TIMEOUT)
+                .send_json(json!({ "description": description }))?;
+            info!("Updated merge request !{}", iid);
+        }
+        None => {
+            let mr: Value = ureq::post(&api)
+                .set("PRIVATE-TOKEN", &token)
+                .timeout(API_TIMEOUT)
+                .send_json(json!({
+                    "source_branch": head,
+                    "target_branch": base,
+                    "title": format!("Draft: {}", title),
+                    "description": description,
+                }))?
+                .into_json()?;
+            info!(
+                "Opened merge request {}",
+                mr["web_url"].as_str().unwrap_or_default()
+            );
+        }
+    }
+    Ok(())
+}
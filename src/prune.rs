@@ -0,0 +1,78 @@
+use crate::{list, logs, state, write_autocommits};
+use color_eyre::Result;
+use std::collections::BTreeSet;
+use tracing::info;
+
+/// Whether `path` is gone or no longer a git repo, e.g. because it was
+/// deleted or moved outside autocommit.
+fn is_dangling(path: &str) -> bool {
+    !std::path::Path::new(path).join(".git").is_dir()
+}
+
+/// Remove the schedule entries, state and logs of repos that no longer exist,
+/// and watched `--discover` directories that are gone. With `dry_run` only
+/// report what would be removed. Returns the repos and directories pruned.
+pub fn prune(dry_run: bool) -> Result<Vec<String>> {
+    let verb = if dry_run { "Would remove" } else { "Removing" };
+    let mut autocommits = list()?;
+    let mut state = state::State::load()?;
+    let mut repos = autocommits
+        .iter()
+        .map(|autocommit| autocommit.repo_path().to_string())
+        .chain(state.repo_paths().cloned())
+        .filter(|repo| is_dangling(repo))
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect::<Vec<_>>();
+
+    let before = autocommits.len();
+    autocommits.retain(|autocommit| {
+        let dangling = is_dangling(autocommit.repo_path());
+        if dangling {
+            info!("{} the schedule of {}", verb, autocommit.repo_path());
+        }
+        !dangling
+    });
+    if !dry_run && autocommits.len() != before {
+        write_autocommits(&autocommits)?;
+    }
+
+    for repo in &repos {
+        let path = std::path::Path::new(repo);
+        if state.get(path).is_some() {
+            info!("{} the state of {}", verb, repo);
+            state.remove(path);
+        }
+        for log in logs::files(path)? {
+            info!("{} {}", verb, log.display());
+            if !dry_run {
+                std::fs::remove_file(&log)?;
+            }
+        }
+    }
+    state.discover.retain(|root| {
+        let exists = std::path::Path::new(&root.dir).is_dir();
+        if !exists {
+            info!("{} the watched directory {}", verb, root.dir);
+            repos.push(root.dir.clone());
+        }
+        exists
+    });
+    if !dry_run {
+        // Apply the removals to the current state, runs may have updated it
+        // since it was read.
+        state::State::update(|state| {
+            for repo in &repos {
+                state.remove(std::path::Path::new(repo));
+            }
+            state
+                .discover
+                .retain(|root| std::path::Path::new(&root.dir).is_dir());
+            Ok(())
+        })?;
+    }
+    if repos.is_empty() {
+        info!("Nothing to prune");
+    }
+    Ok(repos)
+}
@@ -0,0 +1,53 @@
+use crate::config::{self, state_dir};
+use crate::lock::RepoLock;
+use color_eyre::{eyre::eyre, Result};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::debug;
+
+/// How long to wait for another process to hand out its turn.
+const LOCK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Wait for a turn to call the OpenAI API, so that every autocommit process
+/// together stays under `requests_per_minute` from `[generator]`. Turns are
+/// spaced evenly and handed out through a file in the state directory, so
+/// repos scheduled for the same minute queue up instead of hitting 429s.
+pub async fn wait_for_turn() -> Result<()> {
+    let requests_per_minute = config::Config::load()?.generator.requests_per_minute;
+    if requests_per_minute == 0 {
+        return Ok(());
+    }
+    let interval = Duration::from_secs(60) / requests_per_minute;
+    let path = state_dir()?.join("openai-next-request");
+    std::fs::create_dir_all(state_dir()?)?;
+
+    let deadline = tokio::time::Instant::now() + LOCK_TIMEOUT;
+    let lock = loop {
+        if let Some(lock) = RepoLock::acquire_file(path.with_extension("lock"))? {
+            break lock;
+        }
+        if tokio::time::Instant::now() >= deadline {
+            return Err(eyre!("Timed out waiting for the rate limit lock"));
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    };
+    // The file holds the earliest time the next request may start, in
+    // milliseconds since the epoch.
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as u64;
+    let next = std::fs::read_to_string(&path)
+        .ok()
+        .and_then(|next| next.trim().parse::<u64>().ok())
+        .unwrap_or_default();
+    let turn = next.max(now);
+    std::fs::write(&path, (turn + interval.as_millis() as u64).to_string())?;
+    drop(lock);
+
+    if turn > now {
+        let wait = Duration::from_millis(turn - now);
+        debug!(
+            "Waiting {:.1}s for an OpenAI request turn",
+            wait.as_secs_f64()
+        );
+        tokio::time::sleep(wait).await;
+    }
+    Ok(())
+}
@@ -0,0 +1,988 @@
+use crate::{
+    capped_diff, changed_files, config, dirty_for, git_diff, has_changes, has_staged_changes,
+    is_pushed, junk, lock, logs, metrics, notes, notify, output_with_timeout, push_branch,
+    push_refspec, run_checked_command_in_dir, run_command_in_dir, should_push, snapshot, split,
+    squash, state, tags, telemetry, trailers, with_deadline, working_tree, DiffSource, Generation,
+    MessageGenerator, PushPolicy, RepoOptions, SKIP_FILE,
+};
+use color_eyre::{
+    eyre::{eyre, WrapErr},
+    Result,
+};
+use derive_more::Display;
+use std::process::Command;
+use tracing::{debug, error, info, info_span, warn, Instrument};
+
+#[derive(Debug, Default)]
+pub struct RunOptions {
+    pub dry_run: bool,
+    /// Explicit commit message, bypassing generation.
+    pub message: Option<String>,
+    /// Let the user edit the message in their editor before committing.
+    pub edit: bool,
+    /// Prompt for approval and file selection before committing.
+    pub interactive: bool,
+    pub repo: RepoOptions,
+}
+
+/// Open the user's git editor on `message`, with `diff` shown as comments,
+/// the same way `git commit` does. Returns `None` if the message was emptied.
+pub fn edit_message(
+    repo_path: &std::path::PathBuf,
+    message: &str,
+    diff: &str,
+) -> Result<Option<String>> {
+    // `git var` resolves GIT_EDITOR, core.editor, VISUAL and EDITOR in git's order.
+    let editor = run_command_in_dir(repo_path, "git", &["var", "GIT_EDITOR"])?;
+    let editor = editor.trim();
+    if editor.is_empty() {
+        return Err(eyre!("No editor configured, set $EDITOR or core.editor"));
+    }
+
+    let edit_path = repo_path.join(".git").join("AUTOCOMMIT_EDITMSG");
+    let mut contents = format!(
+        "{}\n\n# Edit the generated commit message above. Lines starting with '#'\n# are ignored, and an empty message aborts the commit.\n#\n",
+        message
+    );
+    for line in diff.lines() {
+        contents.push_str("# ");
+        contents.push_str(line);
+        contents.push('\n');
+    }
+    std::fs::write(&edit_path, contents)?;
+
+    // The editor may include arguments, so let the shell split it like git does.
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg(format!("{} \"$@\"", editor))
+        .arg(editor)
+        .arg(&edit_path)
+        .current_dir(repo_path)
+        .status()?;
+    if !status.success() {
+        return Err(eyre!("Editor exited with {}", status));
+    }
+
+    let edited = std::fs::read_to_string(&edit_path)?;
+    std::fs::remove_file(&edit_path)?;
+    let edited = edited
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let edited = edited.trim();
+    Ok((!edited.is_empty()).then(|| edited.to_string()))
+}
+
+/// Let the user accept, regenerate or edit the message and choose which files
+/// to commit. Returns `None` if the user aborted.
+pub async fn review_interactively(
+    repo_path: &std::path::PathBuf,
+    generator: &dyn MessageGenerator,
+    mut commit_message: String,
+    mut files: Vec<String>,
+) -> Result<Option<(String, Vec<String>)>> {
+    use dialoguer::{theme::ColorfulTheme, MultiSelect, Select};
+
+    if !console::user_attended() {
+        return Err(eyre!("--interactive needs to be run from a terminal"));
+    }
+
+    let theme = ColorfulTheme::default();
+    loop {
+        eprintln!(
+            "\nCommit message:\n  {}",
+            commit_message.replace('\n', "\n  ")
+        );
+        eprintln!("Files:");
+        for file in files.iter() {
+            eprintln!("  {}", file);
+        }
+
+        let choice = Select::with_theme(&theme)
+            .with_prompt("What now?")
+            .items(&[
+                "Accept",
+                "Regenerate message",
+                "Edit message",
+                "Select files",
+                "Abort",
+            ])
+            .default(0)
+            .interact()?;
+        match choice {
+            0 => return Ok(Some((commit_message, files))),
+            1 => {
+                let mut args = vec!["diff", "HEAD", "--"];
+                args.extend(files.iter().map(|f| f.as_str()));
+                let diff = capped_diff(repo_path, &args)?;
+                (commit_message, _) = generate_message(generator, repo_path, &diff).await?;
+            }
+            2 => {
+                let diff = git_diff(repo_path, DiffSource::All)?;
+                match edit_message(repo_path, &commit_message, &diff)? {
+                    Some(message) => commit_message = message,
+                    None => eprintln!("Empty message, keeping the previous one"),
+                }
+            }
+            3 => {
+                let all_files = changed_files(repo_path)?;
+                let selected = all_files
+                    .iter()
+                    .map(|f| files.contains(f))
+                    .collect::<Vec<_>>();
+                let chosen = MultiSelect::with_theme(&theme)
+                    .with_prompt("Files to commit (space to toggle)")
+                    .items(&all_files)
+                    .defaults(&selected)
+                    .interact()?;
+                if chosen.is_empty() {
+                    eprintln!("No files selected, keeping the previous selection");
+                } else {
+                    files = chosen.into_iter().map(|i| all_files[i].clone()).collect();
+                }
+            }
+            _ => return Ok(None),
+        }
+    }
+}
+
+/// Run, then report the result to the configured monitoring and notification
+/// sinks and record it in the state file.
+pub async fn run_and_report(
+    repo_path: std::path::PathBuf,
+    options: &RunOptions,
+    generator: &dyn MessageGenerator,
+) -> Result<RunOutcome> {
+    let healthcheck = options
+        .repo
+        .healthcheck
+        .as_deref()
+        .filter(|_| !options.dry_run);
+    if let Some(url) = healthcheck {
+        notify::ping_healthcheck(url, notify::Ping::Start);
+    }
+
+    // Reporting goes on with the defaults if the config can't be read, the
+    // run itself fails on it and that is what gets reported.
+    let config = config::Config::load().unwrap_or_else(|e| {
+        warn!(error = %format!("{:#}", e), "Could not load the config");
+        config::Config::default()
+    });
+    // Interactive runs wait on the user, so only the phase limits apply.
+    let timeout = config
+        .timeouts
+        .run()
+        .filter(|_| !options.interactive && !options.edit);
+    let result = match timeout {
+        Some(limit) => {
+            let deadline = std::time::Instant::now() + limit;
+            let run = tokio::time::timeout(limit, run(repo_path.clone(), options, generator));
+            match with_deadline(deadline, run).await {
+                Ok(Ok(outcome)) => Ok(outcome),
+                // Commands still running at the deadline were killed, which
+                // is the run timing out too.
+                Ok(Err(e)) if std::time::Instant::now() >= deadline => {
+                    Err(e.wrap_err(Failure::Timeout))
+                }
+                Ok(Err(e)) => Err(e),
+                Err(_) => Err(eyre!("The run took longer than {}s", limit.as_secs()))
+                    .wrap_err(Failure::Timeout),
+            }
+        }
+        None => run(repo_path.clone(), options, generator).await,
+    };
+    match &result {
+        Err(e) => {
+            error!(repo = %repo_path.display(), error = %format!("{:#}", e), "Run failed");
+            telemetry::capture_failure(&repo_path, Failure::phase_of(e), e);
+        }
+        Ok(RunOutcome::Committed {
+            push_error: Some(error),
+            ..
+        }) => telemetry::capture_failure(&repo_path, "push", &eyre!("{}", error)),
+        Ok(_) => {}
+    }
+
+    if let Some(url) = healthcheck {
+        match failure_message(&result) {
+            None => notify::ping_healthcheck(url, notify::Ping::Success),
+            Some(error) => notify::ping_healthcheck(url, notify::Ping::Fail(error)),
+        }
+    }
+    notify::notify_run(&repo_path, &options.repo, &result);
+    if !options.dry_run {
+        // Failing to record the run must not hide how it went.
+        let recorded = state::State::update(|state| {
+            let repo_state = state.repo(&repo_path);
+            repo_state.record(&result);
+            Ok(repo_state.clone())
+        });
+        match recorded {
+            Ok(repo_state) => {
+                if let Some(metrics) = &config.metrics {
+                    metrics::push_metrics(metrics, &repo_path, &repo_state, &result);
+                }
+                notify::alert_repeated_failures(
+                    &repo_path,
+                    &config,
+                    repo_state.consecutive_failures,
+                    &result,
+                );
+            }
+            Err(e) => {
+                warn!(error = %format!("{:#}", e), "Could not record the run in the state file")
+            }
+        }
+        run_post_commit(&repo_path, &config.repo(&repo_path), &result);
+    }
+    result
+}
+
+/// Run the repo's `post_commit` and, if the commit was pushed, `post_push`
+/// commands. Failures are only logged, the commit has already been made.
+pub fn run_post_commit(
+    repo_path: &std::path::PathBuf,
+    settings: &config::RepoSettings,
+    result: &Result<RunOutcome>,
+) {
+    let Ok(RunOutcome::Committed {
+        sha,
+        branch,
+        message,
+        pushed,
+        ..
+    }) = result
+    else {
+        return;
+    };
+    let hooks = if *pushed {
+        [&settings.post_commit[..], &settings.post_push[..]].concat()
+    } else {
+        settings.post_commit.clone()
+    };
+    for command in hooks {
+        info!("Running post-commit command {}", command);
+        let output = output_with_timeout(
+            Command::new("sh")
+                .arg("-c")
+                .arg(&command)
+                .current_dir(repo_path)
+                .env("AUTOCOMMIT_REPO", repo_path)
+                .env("AUTOCOMMIT_SHA", sha)
+                .env("AUTOCOMMIT_BRANCH", branch)
+                .env("AUTOCOMMIT_MESSAGE", message),
+            None,
+        );
+        match output {
+            Ok(output) if output.status.success() => {}
+            Ok(output) => error!(
+                command = %command,
+                status = %output.status,
+                stderr = %String::from_utf8_lossy(&output.stderr).trim(),
+                "Post-commit command failed"
+            ),
+            Err(e) => error!(command = %command, error = %e, "Could not run post-commit command"),
+        }
+    }
+}
+
+/// Whether a run folds its changes into the previous autocommit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Amend {
+    No,
+    /// The previous autocommit only exists locally.
+    Unpushed,
+    /// The previous autocommit was pushed, so the amended commit has to be
+    /// force pushed.
+    Pushed,
+}
+
+/// Amend if HEAD is the commit the last run made, it was authored within the
+/// amend window (amending keeps the author date, so the window does not slide)
+/// and it is either unpushed or force pushing is allowed.
+pub fn amend_mode(repo_path: &std::path::PathBuf, repo_options: &RepoOptions) -> Result<Amend> {
+    let Some(window) = repo_options.amend_within else {
+        return Ok(Amend::No);
+    };
+
+    let head = run_command_in_dir(repo_path, "git", &["rev-parse", "HEAD"])?;
+    let state = state::State::load()?;
+    let last_commit = state
+        .get(repo_path)
+        .and_then(|repo_state| repo_state.last_commit.as_deref());
+    if last_commit != Some(head.trim()) {
+        return Ok(Amend::No);
+    }
+    // Never amend a root commit, there is nothing to diff the message against.
+    if run_checked_command_in_dir(repo_path, "git", &["rev-parse", "--verify", "HEAD~1"]).is_err() {
+        return Ok(Amend::No);
+    }
+
+    let authored_at: i64 = run_command_in_dir(repo_path, "git", &["log", "-1", "--format=%at"])?
+        .trim()
+        .parse()?;
+    if chrono::Utc::now().timestamp() - authored_at > i64::from(window) * 60 {
+        return Ok(Amend::No);
+    }
+
+    if !is_pushed(repo_path, "HEAD")? {
+        Ok(Amend::Unpushed)
+    } else if repo_options.force_with_lease && repo_options.push_policy() == PushPolicy::Always {
+        Ok(Amend::Pushed)
+    } else {
+        Ok(Amend::No)
+    }
+}
+
+/// What went wrong in a failed run, attached to its error so [`exit_code`]
+/// can tell failures apart.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display)]
+pub enum Failure {
+    #[display(fmt = "Generating the commit message failed")]
+    Generate,
+    #[display(fmt = "Timed out")]
+    Timeout,
+}
+
+impl Failure {
+    /// The phase a run failed in, as its spans name it.
+    pub fn phase_of(error: &color_eyre::Report) -> &'static str {
+        match error.downcast_ref::<Failure>() {
+            Some(Failure::Generate) => "generate_message",
+            Some(Failure::Timeout) => "timeout",
+            None => "run",
+        }
+    }
+}
+
+/// Generate a message for `diff` in the way the repo's `profile` asks for,
+/// giving up once the `generate_secs` timeout passes.
+pub async fn generate_message(
+    generator: &dyn MessageGenerator,
+    repo_path: &std::path::Path,
+    diff: &str,
+) -> Result<(String, Generation)> {
+    let config = config::Config::load()?;
+    let generation = generator
+        .generate_for(diff, config.repo(repo_path).profile)
+        .instrument(info_span!("generate_message", phase = "generate_message"));
+    let result = match config.timeouts.generate() {
+        Some(limit) => tokio::time::timeout(limit, generation)
+            .await
+            .unwrap_or_else(|_| Err(eyre!("No message after {}s", limit.as_secs()))),
+        None => generation.await,
+    };
+    result.wrap_err(Failure::Generate)
+}
+
+/// The exit codes of `run`, shown in its `--help`.
+pub const EXIT_CODES_HELP: &str = "Exit codes:
+  0  committed, or --dry-run
+  1  nothing to commit
+  2  git or other error
+  3  committed, but pushing failed
+  4  committed with a placeholder message, the model returned none
+  5  generating the commit message failed, nothing was committed
+  6  skipped (disabled, another run in progress, nothing new) or aborted
+  7  the run took longer than its timeout and was aborted";
+
+/// The process exit code for the result of a run, see [`EXIT_CODES_HELP`].
+pub fn exit_code(result: &Result<RunOutcome>) -> i32 {
+    match result {
+        Ok(RunOutcome::Committed {
+            push_error: Some(_),
+            ..
+        }) => 3,
+        Ok(RunOutcome::Committed { generation, .. }) if generation.fallback => 4,
+        Ok(RunOutcome::Committed { .. }) | Ok(RunOutcome::DryRun { .. }) => 0,
+        Ok(RunOutcome::NothingToCommit) => 1,
+        Ok(RunOutcome::Skipped { .. }) | Ok(RunOutcome::Aborted) => 6,
+        Err(e) => match e.downcast_ref::<Failure>() {
+            Some(Failure::Generate) => 5,
+            Some(Failure::Timeout) => 7,
+            None => 2,
+        },
+    }
+}
+
+/// What went wrong in a run, if anything: its error, or why pushing the
+/// commit it made failed.
+pub fn failure_message(result: &Result<RunOutcome>) -> Option<String> {
+    match result {
+        Ok(RunOutcome::Committed {
+            push_error: Some(error),
+            ..
+        }) => Some(format!("Pushing failed: {}", error)),
+        Ok(_) => None,
+        Err(e) => Some(format!("{:#}", e)),
+    }
+}
+
+/// A short block describing the run, printed at its end so cron mails are
+/// readable. `None` for runs that had nothing to do, so idle runs stay silent.
+pub fn run_summary(
+    repo_path: &std::path::Path,
+    result: &Result<RunOutcome>,
+    elapsed: std::time::Duration,
+) -> Option<String> {
+    let mut lines = vec![format!("autocommit {}", repo_path.display())];
+    match result {
+        Ok(RunOutcome::Committed {
+            sha,
+            branch,
+            message,
+            files,
+            amended,
+            pushed,
+            push_duration,
+            push_error,
+            split_commits,
+            ..
+        }) => {
+            lines.push(format!("  branch: {}", branch));
+            lines.push(format!(
+                "  commit: {} {}{}",
+                &sha[..sha.len().min(10)],
+                message.lines().next().unwrap_or_default(),
+                if *amended { " (amended)" } else { "" }
+            ));
+            if !split_commits.is_empty() {
+                lines.push(format!(
+                    "  split:  {} commits before it",
+                    split_commits.len()
+                ));
+            }
+            lines.push(format!("  files:  {} changed", files.len()));
+            lines.push(match push_duration {
+                _ if push_error.is_some() => {
+                    format!(
+                        "  push:   failed: {}",
+                        push_error.as_deref().unwrap_or_default()
+                    )
+                }
+                Some(duration) if *pushed => {
+                    format!("  push:   pushed in {:.1}s", duration.as_secs_f64())
+                }
+                _ if *pushed => "  push:   pushed".to_string(),
+                _ => "  push:   held back by the push policy".to_string(),
+            });
+        }
+        Ok(RunOutcome::Aborted) => lines.push("  result: aborted".to_string()),
+        Ok(_) => return None,
+        Err(e) => lines.push(format!("  failed: {:#}", e)),
+    }
+    lines.push(format!("  took:   {:.1}s", elapsed.as_secs_f64()));
+    Some(lines.join("\n"))
+}
+
+/// What a dry run would have done, for printing after it. `None` for other
+/// outcomes.
+pub fn dry_run_summary(outcome: &RunOutcome) -> Option<String> {
+    let RunOutcome::DryRun {
+        branch,
+        files,
+        message,
+        groups,
+        push_target,
+        push_policy,
+    } = outcome
+    else {
+        return None;
+    };
+    let mut lines = groups
+        .iter()
+        .enumerate()
+        .map(|(i, group)| format!("Would commit group {}: {}", i + 1, group.join(", ")))
+        .collect::<Vec<_>>();
+    lines.push(match branch {
+        Some(branch) => format!("Would commit to {}:\n{}", branch, files.join("\n")),
+        None => format!("Would commit:\n{}", files.join("\n")),
+    });
+    lines.push(format!("Commit message:\n{}", message));
+    lines.push(match (push_target, push_policy) {
+        (Some(target), Some(policy)) => {
+            format!("Would push to: {} (push policy: {})", target, policy)
+        }
+        (Some(target), None) => format!("Would push to: {}", target),
+        (None, _) => "Would push to: (no push target configured)".to_string(),
+    });
+    Some(lines.join("\n"))
+}
+
+/// What a call to `run` did.
+#[derive(Debug, serde::Serialize)]
+#[serde(tag = "result", rename_all = "snake_case")]
+pub enum RunOutcome {
+    /// The working tree was clean.
+    NothingToCommit,
+    /// The user threw away the commit from the editor or the interactive prompt.
+    Aborted,
+    /// `--dry-run` was given, nothing was changed.
+    DryRun {
+        /// The branch the commit would go to if it isn't the checked-out
+        /// one, e.g. the snapshot branch.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        branch: Option<String>,
+        /// What would be committed, as `git diff --name-status` lists it.
+        files: Vec<String>,
+        message: String,
+        /// The groups `--split` or `--group` would commit the files in.
+        #[serde(skip_serializing_if = "Vec::is_empty")]
+        groups: Vec<Vec<String>>,
+        /// Where the commit would be pushed, `None` if no push target is
+        /// configured.
+        push_target: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        push_policy: Option<String>,
+    },
+    /// The run was skipped without looking at the repo's changes.
+    Skipped { reason: String },
+    Committed {
+        sha: String,
+        branch: String,
+        message: String,
+        files: Vec<String>,
+        /// The changes were folded into the previous autocommit.
+        amended: bool,
+        pushed: bool,
+        #[serde(skip)]
+        push_duration: Option<std::time::Duration>,
+        /// Why pushing failed. The commit was still made and is recorded
+        /// like any other autocommit.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        push_error: Option<String>,
+        /// How long the committed changes sat uncommitted, see [`dirty_for`].
+        #[serde(skip)]
+        dirty_for: Option<std::time::Duration>,
+        generation: Generation,
+        /// Commits made before `sha` when `--split` grouped the changes,
+        /// oldest first.
+        #[serde(skip_serializing_if = "Vec::is_empty")]
+        split_commits: Vec<String>,
+        /// The working tree the commit was made from, see [`working_tree`].
+        /// `None` if the user chose to commit only some of the changed files.
+        #[serde(skip)]
+        tree: Option<String>,
+    },
+}
+
+impl RunOutcome {
+    /// The name used for this outcome in JSON output and the state file.
+    pub fn name(&self) -> &'static str {
+        match self {
+            RunOutcome::NothingToCommit => "nothing_to_commit",
+            RunOutcome::Aborted => "aborted",
+            RunOutcome::DryRun { .. } => "dry_run",
+            RunOutcome::Skipped { .. } => "skipped",
+            RunOutcome::Committed { .. } => "committed",
+        }
+    }
+}
+
+/// Why runs are disabled in this repo, if they are: a `.autocommit-skip` file
+/// at the repo root, or `autocommit.enabled = false` in its git config.
+pub fn disabled_reason(repo_path: &std::path::PathBuf) -> Option<String> {
+    if repo_path.join(SKIP_FILE).exists() {
+        return Some(format!("{} exists", SKIP_FILE));
+    }
+    let enabled = run_command_in_dir(
+        repo_path,
+        "git",
+        &["config", "--type=bool", "autocommit.enabled"],
+    )
+    .ok()?;
+    (enabled.trim() == "false").then(|| "autocommit.enabled is false".to_string())
+}
+
+/// `args` followed by `--` and the pathspecs, for git commands that take them.
+fn with_pathspecs<'a>(args: &[&'a str], pathspecs: &'a [String]) -> Vec<&'a str> {
+    let mut args = args.to_vec();
+    args.push("--");
+    args.extend(pathspecs.iter().map(|p| p.as_str()));
+    args
+}
+
+/// Run the repo's `pre_commit` commands, so formatters and linters get a
+/// chance to fix up the changes before they are committed.
+pub fn run_pre_commit(
+    repo_path: &std::path::PathBuf,
+    settings: &config::RepoSettings,
+) -> Result<()> {
+    for command in &settings.pre_commit {
+        info!("Running pre-commit command {}", command);
+        let output = output_with_timeout(
+            Command::new("sh")
+                .arg("-c")
+                .arg(command)
+                .current_dir(repo_path),
+            None,
+        )?;
+        if output.status.success() {
+            continue;
+        }
+        let error = eyre!(
+            "Pre-commit command {} exited with {}: {}",
+            command,
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+        match settings.on_pre_commit_failure {
+            config::PreCommitFailure::Abort => return Err(error),
+            config::PreCommitFailure::Commit => error!(error = %error, "Committing anyway"),
+        }
+    }
+    Ok(())
+}
+
+#[tracing::instrument(skip_all, fields(repo = %repo_path.display()))]
+pub async fn run(
+    repo_path: std::path::PathBuf,
+    options: &RunOptions,
+    generator: &dyn MessageGenerator,
+) -> Result<RunOutcome> {
+    // Check if the provided path is a git repo.
+    if !repo_path.join(".git").is_dir() {
+        return Err(eyre!("Path is not a git repo"));
+    }
+
+    if let Some(reason) = disabled_reason(&repo_path) {
+        info!("Autocommit is disabled in this repo ({}), skipping", reason);
+        return Ok(RunOutcome::Skipped { reason });
+    }
+
+    // Skip rather than wait if another run is still working on this repo, the
+    // next scheduled run picks up whatever it missed.
+    let _lock = if options.dry_run {
+        None
+    } else {
+        match lock::RepoLock::acquire(&repo_path) {
+            Ok(Some(lock)) => Some(lock),
+            Ok(None) => {
+                info!("Another run is in progress, skipping");
+                return Ok(RunOutcome::Skipped {
+                    reason: "another run is in progress".to_string(),
+                });
+            }
+            // Racing another run for the lock, it will get the work done.
+            Err(e) => {
+                warn!(error = %format!("{:#}", e), "Could not take the repo lock, skipping");
+                return Ok(RunOutcome::Skipped {
+                    reason: format!("could not take the repo lock: {:#}", e),
+                });
+            }
+        }
+    };
+
+    if !options.dry_run {
+        logs::migrate(&repo_path)?;
+        logs::prune(&config::Config::load()?.logging)?;
+    }
+
+    // Junk files are never committed, point them out so they get ignored.
+    junk::check(&repo_path, options.repo.ignore_junk && !options.dry_run)?;
+
+    // A failed tag must not stop today's changes from being committed.
+    if options.repo.tag_daily && !options.dry_run {
+        let push = options.repo.push_policy() != PushPolicy::Never;
+        let rev = if options.repo.snapshot_branch {
+            snapshot::snapshot_ref(&repo_path).map(|(_, rev)| rev)
+        } else {
+            Ok("HEAD".to_string())
+        };
+        if let Err(e) = rev.and_then(|rev| tags::tag_previous_day(&repo_path, &rev, push)) {
+            warn!(error = %format!("{:#}", e), "Tagging the previous day failed");
+        }
+    }
+
+    if options.repo.snapshot_branch {
+        return snapshot::run(&repo_path, options, generator).await;
+    }
+
+    // Snapshot branches never touch the checked-out branch, so only guard
+    // direct commits.
+    let branches = config::Config::load()?.branches;
+    if !options.repo.allow_protected && !branches.allow_protected {
+        let branch = run_command_in_dir(&repo_path, "git", &["rev-parse", "--abbrev-ref", "HEAD"])?;
+        if let Some(pattern) = branches.protecting(branch.trim()) {
+            return Err(eyre!(
+                "{} is a protected branch (matches {}), switch to a work branch or use --allow-protected",
+                branch.trim(),
+                pattern
+            ));
+        }
+    }
+
+    // Squashing is housekeeping, it must not stop today's changes from
+    // being committed.
+    if options.repo.squash_daily && !options.dry_run {
+        if let Err(e) =
+            squash::squash_previous_day(&repo_path, options.repo.force_with_lease, generator).await
+        {
+            warn!(error = %format!("{:#}", e), "Squashing the previous day failed");
+        }
+    }
+
+    // Run `git status` and check if there are any changes.
+    let pathspecs = &options.repo.pathspecs;
+    let staged_only = options.repo.staged_only;
+    let changed = info_span!("status", phase = "status").in_scope(|| {
+        if staged_only {
+            has_staged_changes(&repo_path)
+        } else {
+            has_changes(&repo_path, pathspecs)
+        }
+    })?;
+    if !changed {
+        debug!("no changes");
+        return Ok(RunOutcome::NothingToCommit);
+    }
+
+    // Commands can rewrite files, which a dry run must not do.
+    if !options.dry_run {
+        info_span!("pre_commit", phase = "pre_commit")
+            .in_scope(|| run_pre_commit(&repo_path, &config::Config::load()?.repo(&repo_path)))?;
+    }
+
+    // A failed push or an `undo` can leave behind exactly the changes the last
+    // autocommit already captured, don't snapshot them twice.
+    let tree = if staged_only {
+        run_checked_command_in_dir(&repo_path, "git", &["write-tree"])?
+            .trim()
+            .to_string()
+    } else {
+        working_tree(&repo_path, pathspecs)?
+    };
+    let last_tree = state::State::load()?
+        .get(&repo_path)
+        .and_then(|repo_state| repo_state.last_tree.clone());
+    if last_tree.as_deref() == Some(tree.as_str()) {
+        info!("Nothing new since the last autocommit, skipping");
+        return Ok(RunOutcome::Skipped {
+            reason: "nothing new since the last autocommit".to_string(),
+        });
+    }
+
+    let amend = amend_mode(&repo_path, &options.repo)?;
+    if amend != Amend::No {
+        info!("Amending the previous autocommit");
+    }
+
+    // Run `git diff` to get the output changes. When amending, the message has
+    // to describe the previous autocommit's changes too.
+    let mut diff_args = vec!["diff"];
+    if staged_only {
+        diff_args.push("--cached");
+    }
+    if amend != Amend::No {
+        diff_args.push("HEAD~1");
+    }
+    let git_diff_out = info_span!("diff", phase = "diff")
+        .in_scope(|| capped_diff(&repo_path, &with_pathspecs(&diff_args, pathspecs)))?;
+    debug!("git diff output: {}", git_diff_out);
+
+    // With `--split` or `--group`, commit all but the last group of files
+    // right away. The last group goes through the usual commit below.
+    let mut split_commits = Vec::new();
+    let mut split_files = None;
+    let should_split = (options.repo.split || !options.repo.groups.is_empty())
+        && amend == Amend::No
+        && options.message.is_none()
+        && !options.interactive;
+    let groups = if should_split {
+        split::plan(
+            &repo_path,
+            generator,
+            pathspecs,
+            &options.repo.groups,
+            &git_diff_out,
+        )
+        .await?
+    } else {
+        None
+    };
+    let mut dry_run_groups = Vec::new();
+    let git_diff_out = match groups {
+        Some(groups) if options.dry_run => {
+            dry_run_groups = groups;
+            git_diff_out
+        }
+        Some(mut groups) => {
+            let last = groups.pop().unwrap();
+            split_commits =
+                split::commit_groups(&repo_path, generator, &groups, options.repo.no_verify)
+                    .await?;
+            let diff = capped_diff(&repo_path, &with_pathspecs(&["diff", "HEAD"], &last))?;
+            split_files = Some(last);
+            diff
+        }
+        None => git_diff_out,
+    };
+
+    let (commit_message, generation) = match &options.message {
+        Some(message) => (message.clone(), Generation::default()),
+        None => generate_message(generator, &repo_path, &git_diff_out).await?,
+    };
+    let commit_message = if options.edit {
+        match edit_message(&repo_path, &commit_message, &git_diff_out)? {
+            Some(message) => message,
+            None => {
+                info!("Aborting commit due to empty commit message");
+                return Ok(RunOutcome::Aborted);
+            }
+        }
+    } else {
+        commit_message
+    };
+
+    // `None` means every changed file, as `commit -a` would.
+    let mut selected_files = split_files;
+    // Whether the user left some of the changes out of the commit.
+    let mut partial = false;
+    let commit_message = if options.interactive {
+        let files = changed_files(&repo_path)?;
+        match review_interactively(&repo_path, generator, commit_message, files.clone()).await? {
+            Some((message, chosen)) => {
+                if chosen != files {
+                    selected_files = Some(chosen);
+                    partial = true;
+                }
+                message
+            }
+            None => {
+                info!("Aborting commit");
+                return Ok(RunOutcome::Aborted);
+            }
+        }
+    } else {
+        commit_message
+    };
+    let commit_message =
+        trailers::append(&repo_path, &commit_message, &config::Config::load()?.commit)?;
+    info!("commit message: {}", commit_message);
+
+    if options.dry_run {
+        // `commit -a` picks up every tracked change, staged or not.
+        let mut args = vec!["diff", "--name-status"];
+        if staged_only {
+            args.push("--cached");
+        }
+        args.extend(["HEAD", "--"]);
+        match &selected_files {
+            Some(files) => args.extend(files.iter().map(|f| f.as_str())),
+            None => args.extend(pathspecs.iter().map(|p| p.as_str())),
+        }
+        let files = run_command_in_dir(&repo_path, "git", &args)?;
+        return Ok(RunOutcome::DryRun {
+            branch: None,
+            files: files.lines().map(|line| line.to_string()).collect(),
+            message: commit_message,
+            groups: dry_run_groups,
+            push_target: push_refspec(&repo_path).map(|target| target.to_string()),
+            push_policy: Some(options.repo.push_policy().to_string()),
+        });
+    }
+
+    let commit_span = info_span!("commit", phase = "commit").entered();
+    let mut args = vec!["commit", "-m", &commit_message];
+    if amend != Amend::No {
+        args.push("--amend");
+    }
+    // `git commit` runs the repo's hooks (honoring `core.hooksPath`) and
+    // fails if they reject the commit.
+    if options.repo.no_verify {
+        args.push("--no-verify");
+    }
+    match &selected_files {
+        // Run `git commit -m {commit_message} -- {files}` to commit only the chosen files.
+        Some(files) => {
+            args.push("--");
+            args.extend(files.iter().map(|f| f.as_str()));
+        }
+        // Run `git commit -m {commit_message}` to commit only the index.
+        None if staged_only => {}
+        // Run `git commit -am {commit_message}` to add all changes.
+        None if pathspecs.is_empty() => args.push("-a"),
+        // Run `git commit -m {commit_message} -- {pathspecs}` to commit the
+        // changes to tracked files under the pathspecs.
+        None => {
+            args.push("--");
+            args.extend(pathspecs.iter().map(|p| p.as_str()));
+        }
+    }
+    run_checked_command_in_dir(&repo_path, "git", &args)?;
+    let sha = run_command_in_dir(&repo_path, "git", &["rev-parse", "HEAD"])?
+        .trim()
+        .to_string();
+    let branch = run_command_in_dir(&repo_path, "git", &["rev-parse", "--abbrev-ref", "HEAD"])?
+        .trim()
+        .to_string();
+    let files: Vec<String> = run_command_in_dir(
+        &repo_path,
+        "git",
+        &["show", "--name-only", "--format=", "HEAD"],
+    )?
+    .lines()
+    .filter(|f| !f.is_empty())
+    .map(|f| f.to_string())
+    .collect();
+    let dirty_for = dirty_for(&repo_path, &files, "HEAD^");
+    // The commit exists from here on, so what follows only logs failures.
+    if generation.model.is_some() {
+        if let Err(e) = notes::add(&repo_path, &sha, &generation) {
+            warn!(error = %format!("{:#}", e), "Could not add the generation note");
+        }
+    }
+    commit_span.exit();
+
+    // Run `git push` to push the changes, if the push policy allows it. The
+    // commit is made either way, so a failed push doesn't fail the run.
+    let mut push_error = None;
+    let push = should_push(&repo_path, options.repo.push_policy()).unwrap_or_else(|e| {
+        warn!(error = %format!("{:#}", e), "Could not check the push policy, not pushing");
+        push_error = Some(format!("{:#}", e));
+        false
+    });
+    let push_duration = if push {
+        let _push_span = info_span!("push", phase = "push").entered();
+        let push_timeout = config::Config::load()
+            .unwrap_or_else(|e| {
+                warn!(error = %format!("{:#}", e), "Could not load the config");
+                config::Config::default()
+            })
+            .timeouts
+            .push();
+        let push_start = std::time::Instant::now();
+        match push_branch(&repo_path, amend == Amend::Pushed, push_timeout) {
+            Ok(_) => Some(push_start.elapsed()),
+            Err(e) => {
+                error!(error = %format!("{:#}", e), "Push failed, the commit stays local");
+                push_error = Some(format!("{:#}", e));
+                None
+            }
+        }
+    } else {
+        if push_error.is_none() {
+            info!(
+                "Not pushing yet (push policy: {})",
+                options.repo.push_policy()
+            );
+        }
+        None
+    };
+
+    Ok(RunOutcome::Committed {
+        tree: (!partial).then_some(tree),
+        split_commits,
+        sha,
+        branch,
+        message: commit_message,
+        files,
+        amended: amend != Amend::No,
+        pushed: push_duration.is_some(),
+        push_duration,
+        push_error,
+        dirty_for,
+        generation,
+    })
+}
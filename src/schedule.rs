@@ -0,0 +1,810 @@
+use crate::{auth, config, logs, run_command_in_dir, state, RepoOptions, COMMAND_NAME};
+use chrono::{DateTime, Local, TimeZone, Timelike};
+use clap::Args;
+use color_eyre::{eyre::eyre, Result};
+use derive_more::Display;
+use std::env;
+use std::fs::{canonicalize, File};
+use std::io::{Read, Write};
+use std::process::{Command, Stdio};
+use tracing::{debug, info, warn};
+
+/// How often `create` schedules runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display, serde::Deserialize, serde::Serialize)]
+#[serde(untagged)]
+pub enum Frequency {
+    #[display(fmt = "every {} minutes", _0)]
+    Minutes(u32),
+    #[display(fmt = "daily at {:02}:{:02}", hour, minute)]
+    Daily { hour: u32, minute: u32 },
+    #[display(fmt = "hourly at :{:02}", minute)]
+    Hourly { minute: u32 },
+}
+
+impl Frequency {
+    /// The crontab schedule fields.
+    pub fn fields(&self) -> [String; 5] {
+        let (minute, hour) = match self {
+            Frequency::Minutes(minutes) => (format!("*/{}", minutes), "*".to_string()),
+            Frequency::Daily { hour, minute } => (minute.to_string(), hour.to_string()),
+            Frequency::Hourly { minute } => (minute.to_string(), "*".to_string()),
+        };
+        [
+            minute,
+            hour,
+            "*".to_string(),
+            "*".to_string(),
+            "*".to_string(),
+        ]
+    }
+}
+
+/// When `create` schedules runs, one of an interval, a time of day or a preset.
+#[derive(Args, Debug, Clone)]
+#[group(required = true, multiple = false)]
+pub struct ScheduleArgs {
+    /// Minutes between autocommits.
+    #[clap(long, short = 'f')]
+    pub frequency: Option<u32>,
+
+    /// Run once a day at this time, e.g. `18:00` for an end-of-day snapshot.
+    #[clap(long, value_parser = parse_time)]
+    pub at: Option<Frequency>,
+
+    /// Run once a day, at midnight.
+    #[clap(long)]
+    pub daily: bool,
+
+    /// Run once an hour, on the hour.
+    #[clap(long)]
+    pub hourly: bool,
+}
+
+impl ScheduleArgs {
+    pub fn frequency(&self) -> Frequency {
+        match (self.frequency, self.at) {
+            (Some(minutes), _) => Frequency::Minutes(minutes),
+            (_, Some(at)) => at,
+            _ if self.hourly => Frequency::Hourly { minute: 0 },
+            _ => Frequency::Daily { hour: 0, minute: 0 },
+        }
+    }
+}
+
+/// Parse an `HH:MM` time of day for `--at`.
+fn parse_time(time: &str) -> Result<Frequency, String> {
+    let time = chrono::NaiveTime::parse_from_str(time, "%H:%M")
+        .map_err(|_| format!("expected a time like 18:00, got {}", time))?;
+    Ok(Frequency::Daily {
+        hour: time.hour(),
+        minute: time.minute(),
+    })
+}
+
+#[derive(Debug, Default, Display, Clone)]
+#[display(fmt = "{:?} {:?} {:?}", frequency, command, args)]
+pub struct CronLine {
+    pub frequency: [String; 5],
+    pub command: String,
+    pub args: Vec<String>,
+    /// Paused autocommits are kept in the crontab, commented out.
+    pub paused: bool,
+}
+
+impl CronLine {
+    pub fn new(frequency: [String; 5], command: String, args: Vec<String>) -> Self {
+        Self {
+            frequency,
+            command,
+            args,
+            paused: false,
+        }
+    }
+
+    pub fn parse(line: &str) -> Result<CronLine> {
+        let mut cron_line = CronLine::default();
+        let line = match line.trim_start().strip_prefix('#') {
+            Some(rest) => {
+                cron_line.paused = true;
+                rest
+            }
+            None => line,
+        };
+        // Cron turns a bare `%` into a newline, so `to_string` escapes them.
+        let line = line.replace("\\%", "%");
+        for (i, part) in shell_split(&line)?.into_iter().enumerate() {
+            match i {
+                0..=4 => cron_line.frequency[i] = part,
+                5 => cron_line.command = part,
+                _ => cron_line.args.push(part),
+            }
+        }
+
+        if cron_line.command.is_empty() || cron_line.args.is_empty() {
+            return Err(eyre!("Invalid cron line, missing parts "));
+        }
+
+        for part in cron_line.frequency.iter() {
+            if part.is_empty() {
+                return Err(eyre!("Invalid cron line frequency, missing parts "));
+            }
+        }
+
+        Ok(cron_line)
+    }
+
+    pub fn to_string(&self) -> String {
+        format!(
+            "{}{} {}",
+            if self.paused { "# " } else { "" },
+            self.frequency.join(" "),
+            self.shell_command().replace('%', "\\%")
+        )
+    }
+
+    /// The shell command cron runs, including the log redirection, with each
+    /// argument quoted for `/bin/sh`.
+    pub fn shell_command(&self) -> String {
+        std::iter::once(&self.command)
+            .chain(&self.args)
+            .map(|arg| shell_quote(arg))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// The repo this autocommit runs on.
+    pub fn repo_path(&self) -> &str {
+        self.args.get(1).map(|a| a.as_str()).unwrap_or_default()
+    }
+
+    /// The file the scheduled run logs to. Older entries redirected their
+    /// output into the repo instead of using the per-repo log.
+    pub fn log_path(&self) -> Result<std::path::PathBuf> {
+        match self.args.iter().position(|a| a == ">>") {
+            Some(redirect) if redirect + 1 < self.args.len() => {
+                Ok(std::path::PathBuf::from(&self.args[redirect + 1]))
+            }
+            _ => logs::log_path(std::path::Path::new(self.repo_path())),
+        }
+    }
+
+    /// The parsed schedule. The `cron` crate wants a leading seconds field and
+    /// numbers days of the week from 1 (Sunday) rather than 0.
+    pub fn schedule(&self) -> Result<cron::Schedule> {
+        let mut fields = self.frequency.clone();
+        let mut days: Vec<String> = Vec::new();
+        for part in fields[4].split(',') {
+            let parts = match cron_days(part) {
+                Some(numbers) => numbers.iter().map(|day| day.to_string()).collect(),
+                None => vec![part.to_string()],
+            };
+            for part in parts {
+                if !days.contains(&part) {
+                    days.push(part);
+                }
+            }
+        }
+        fields[4] = days.join(",");
+        format!("0 {}", fields.join(" "))
+            .parse()
+            .map_err(|e| eyre!("Invalid schedule {}: {}", self.frequency.join(" "), e))
+    }
+
+    /// When the schedule fires next.
+    pub fn next_run(&self, now: DateTime<Local>) -> Option<DateTime<Local>> {
+        self.schedule().ok()?.after(&now).next()
+    }
+
+    /// Whether the schedule fired between `since` and `now`. Runs due in the
+    /// last minute are left to cron.
+    pub fn missed_since(&self, since: DateTime<Local>, now: DateTime<Local>) -> bool {
+        self.schedule()
+            .ok()
+            .and_then(|schedule| schedule.after(&since).next())
+            .map_or(false, |due| due < now - chrono::Duration::minutes(1))
+    }
+
+    /// The schedule in words for the shapes `create` writes, the cron
+    /// expression otherwise.
+    pub fn describe(&self) -> String {
+        let frequency = &self.frequency;
+        let any_from = |field: usize| frequency[field..].iter().all(|f| f == "*");
+        if let Some(step) = frequency[0].strip_prefix("*/") {
+            if any_from(1) {
+                return format!("every {} minutes", step);
+            }
+        }
+        match (frequency[0].parse::<u32>(), frequency[1].parse::<u32>()) {
+            (Ok(minute), Ok(hour)) if any_from(2) => format!("daily at {:02}:{:02}", hour, minute),
+            (Ok(minute), _) if any_from(1) => format!("hourly at :{:02}", minute),
+            _ => frequency.join(" "),
+        }
+    }
+
+    /// What is wrong with this entry: a schedule that doesn't parse, or a repo
+    /// that was deleted or moved.
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        if let Err(e) = self.schedule() {
+            problems.push(e.to_string());
+        }
+        if !std::path::Path::new(self.repo_path()).join(".git").is_dir() {
+            problems.push(format!("{} is not a git repo anymore", self.repo_path()));
+        }
+        problems
+    }
+}
+
+/// The days of the week a numeric crontab day field part (`5`, `1-5`,
+/// `1-7/2`) stands for, numbered the way the `cron` crate does. Ranges are
+/// expanded first, so `5-7` keeps ending on Sunday. `None` for `*`, names and
+/// anything invalid, which are passed on as they are.
+fn cron_days(part: &str) -> Option<Vec<u32>> {
+    let (days, step) = match part.split_once('/') {
+        Some((days, step)) => (days, Some(step.parse::<usize>().ok()?)),
+        None => (part, None),
+    };
+    let (first, last) = match (days.split_once('-'), step) {
+        (Some((first, last)), _) => (first.parse::<u32>().ok()?, last.parse().ok()?),
+        // `5/2` steps from 5 to the end of the week.
+        (None, Some(_)) => (days.parse().ok()?, 7),
+        (None, None) => {
+            let day = days.parse().ok()?;
+            (day, day)
+        }
+    };
+    if first > last || last > 7 || step == Some(0) {
+        return None;
+    }
+    Some(
+        (first..=last)
+            .step_by(step.unwrap_or(1))
+            .map(|day| day % 7 + 1)
+            .collect(),
+    )
+}
+
+// TODO: this prevents the user from running other cron jobs rn :(
+pub fn write_autocommits(autocommits: &Vec<CronLine>) -> Result<()> {
+    // The OpenAI key is read at run time (from the keyring or the
+    // environment) rather than written into the crontab.
+    migrate_crontab_key()?;
+    let mut file = File::create("/tmp/crontab.txt")?;
+    let data = autocommits
+        .iter()
+        .map(|a| a.to_string())
+        .collect::<Vec<String>>()
+        .join("\n")
+        + "\n";
+    file.write_all(data.as_bytes())?;
+
+    // Create cron.
+    Command::new("crontab").arg("/tmp/crontab.txt").spawn()?;
+    Ok(())
+}
+
+/// Older versions wrote an `OPENAI_API_KEY=` line into the crontab, which
+/// rewriting it drops. Move the key into the keyring first, unless it
+/// already has one.
+fn migrate_crontab_key() -> Result<()> {
+    let crontab = read_crontab()?;
+    let Some(key) = crontab
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("OPENAI_API_KEY="))
+    else {
+        return Ok(());
+    };
+    let key = key.trim_matches(|c| c == '"' || c == '\'');
+    match auth::is_set() {
+        Ok(true) => info!("Dropping OPENAI_API_KEY from the crontab, the keyring has a key"),
+        Ok(false) => match auth::set(key) {
+            Ok(()) => info!("Moved OPENAI_API_KEY from the crontab into the keyring"),
+            Err(e) => warn!(
+                "Could not move OPENAI_API_KEY from the crontab into the keyring, scheduled runs \
+                 will have no OpenAI key until you run `autocommit auth set`: {:#}",
+                e
+            ),
+        },
+        Err(e) => warn!(
+            "Could not check the keyring for an OpenAI key, the crontab's OPENAI_API_KEY is \
+             dropped; run `autocommit auth set` if scheduled runs have no key: {:#}",
+            e
+        ),
+    }
+    Ok(())
+}
+
+fn read_crontab() -> Result<String> {
+    let command = Command::new("crontab")
+        .arg("-l")
+        .stdout(Stdio::piped())
+        .spawn()?;
+    let mut command_output = String::new();
+    command
+        .stdout
+        .unwrap()
+        .read_to_string(&mut command_output)?;
+    Ok(command_output)
+}
+
+pub fn list() -> Result<Vec<CronLine>> {
+    let command_output = read_crontab()?;
+    let lines = command_output.lines();
+    let mut autocommits = Vec::new();
+    for line in lines {
+        if line.contains(COMMAND_NAME) {
+            match CronLine::parse(line) {
+                Ok(cron_line) => autocommits.push(cron_line),
+                // Comments that merely mention autocommit are not paused entries.
+                Err(e) if line.trim_start().starts_with('#') => {
+                    debug!("Skipping comment {}: {}", line, e)
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+    Ok(autocommits)
+}
+
+/// Autocommits whose schedule fired since their last successful run without
+/// them running, e.g. while the machine was asleep. Repos that never ran
+/// successfully are left to their schedule.
+pub fn missed(now: DateTime<Local>) -> Result<Vec<CronLine>> {
+    let state = state::State::load()?;
+    Ok(list()?
+        .into_iter()
+        .filter(|line| !line.paused)
+        .filter(|line| {
+            state
+                .get(std::path::Path::new(line.repo_path()))
+                .and_then(|repo_state| repo_state.last_success_at)
+                .and_then(|at| Local.timestamp_opt(at, 0).single())
+                .map_or(false, |at| line.missed_since(at, now))
+        })
+        .collect())
+}
+
+/// Run every autocommit that missed its schedule now, one after another, the
+/// way cron would have.
+pub fn catchup(dry_run: bool) -> Result<()> {
+    let missed = missed(Local::now())?;
+    if missed.is_empty() {
+        info!("No missed runs");
+        return Ok(());
+    }
+    for line in missed {
+        if dry_run {
+            info!("Would catch up on {}", line.repo_path());
+            continue;
+        }
+        info!("Catching up on {}", line.repo_path());
+        let status = Command::new("sh")
+            .arg("-c")
+            .arg(line.shell_command())
+            .status()?;
+        if !status.success() {
+            info!("Run on {} exited with {}", line.repo_path(), status);
+        }
+    }
+    Ok(())
+}
+
+/// A configured autocommit together with what the state file knows about it.
+#[derive(Debug, serde::Serialize)]
+pub struct RepoStatus {
+    pub repo: String,
+    pub schedule: String,
+    pub paused: bool,
+    /// `None` if the repo could not be inspected, e.g. it was moved.
+    pub dirty: Option<bool>,
+    pub last_run_at: Option<i64>,
+    pub last_result: Option<String>,
+    pub last_commit: Option<String>,
+    pub last_message: Option<String>,
+    pub last_error: Option<String>,
+    pub consecutive_failures: u32,
+    /// Average seconds between a change and the autocommit that captured it.
+    pub avg_dirty_secs: Option<u64>,
+}
+
+impl std::fmt::Display for RepoStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let dirty = match self.dirty {
+            Some(true) => "dirty",
+            Some(false) => "clean",
+            None => "missing",
+        };
+        write!(f, "{} [{}] {}", self.repo, self.schedule, dirty)?;
+        if self.paused {
+            write!(f, " (paused)")?;
+        }
+        match (self.last_run_at, &self.last_result) {
+            (Some(at), Some(result)) => {
+                let at = Local
+                    .timestamp_opt(at, 0)
+                    .single()
+                    .map(|at| at.format("%Y-%m-%d %H:%M").to_string())
+                    .unwrap_or_default();
+                write!(f, ", last run {} {}", at, result)?;
+            }
+            _ => write!(f, ", never run")?,
+        }
+        if let Some(message) = &self.last_message {
+            write!(
+                f,
+                ", last commit: {}",
+                message.lines().next().unwrap_or_default()
+            )?;
+        }
+        if let Some(secs) = self.avg_dirty_secs {
+            write!(
+                f,
+                ", avg {} between change and snapshot",
+                format_dirty_secs(secs)
+            )?;
+        }
+        if self.consecutive_failures > 0 {
+            write!(
+                f,
+                ", {} consecutive failures: {}",
+                self.consecutive_failures,
+                self.last_error.as_deref().unwrap_or_default()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// A short duration like `45s`, `7m` or `2h10m`.
+fn format_dirty_secs(secs: u64) -> String {
+    match secs {
+        0..=59 => format!("{}s", secs),
+        60..=3599 => format!("{}m", secs / 60),
+        _ if secs % 3600 < 60 => format!("{}h", secs / 3600),
+        _ => format!("{}h{}m", secs / 3600, secs % 3600 / 60),
+    }
+}
+
+/// Whether the repo has uncommitted changes, `None` if it could not be
+/// inspected, e.g. because it was moved.
+pub fn repo_dirty(repo_path: &std::path::PathBuf) -> Option<bool> {
+    if !repo_path.join(".git").is_dir() {
+        return None;
+    }
+    run_command_in_dir(repo_path, "git", &["status", "--porcelain"])
+        .ok()
+        .map(|status| !status.trim().is_empty())
+}
+
+/// Status of every configured autocommit, or only the one on `repo_path`.
+pub fn status(repo_path: Option<&std::path::Path>) -> Result<Vec<RepoStatus>> {
+    let state = state::State::load()?;
+    let mut statuses = Vec::new();
+    for autocommit in list()? {
+        let path = std::path::PathBuf::from(autocommit.repo_path());
+        if repo_path.map_or(false, |p| p != path) {
+            continue;
+        }
+        let dirty = repo_dirty(&path);
+        let repo_state = state.get(&path);
+        statuses.push(RepoStatus {
+            repo: autocommit.repo_path().to_string(),
+            schedule: autocommit.frequency.join(" "),
+            paused: autocommit.paused,
+            dirty,
+            last_run_at: repo_state.and_then(|s| s.last_run_at),
+            last_result: repo_state.and_then(|s| s.last_result.clone()),
+            last_commit: repo_state.and_then(|s| s.last_commit.clone()),
+            last_message: repo_state.and_then(|s| s.last_message.clone()),
+            last_error: repo_state.and_then(|s| s.last_error.clone()),
+            consecutive_failures: repo_state.map_or(0, |s| s.consecutive_failures),
+            avg_dirty_secs: repo_state.and_then(|s| s.avg_dirty_secs()),
+        });
+    }
+    if let (Some(repo_path), true) = (repo_path, statuses.is_empty()) {
+        return Err(eyre!(
+            "Autocommit not found on path {}",
+            repo_path.display()
+        ));
+    }
+    Ok(statuses)
+}
+
+pub fn create(
+    path: &std::path::PathBuf,
+    frequency: Frequency,
+    repo_options: &RepoOptions,
+) -> Result<()> {
+    let path = canonicalize(path)?;
+    if !path.join(".git").is_dir() {
+        return Err(eyre!("Path is not a git repo"));
+    }
+    info!(
+        "Creating autocommit on {} running {}",
+        path.display(),
+        frequency
+    );
+    // Check if autocommit exists on path.
+    let mut autocommits = list()?;
+    for autocommit in autocommits.iter() {
+        // TODO: make this conditional better, and less error prone.
+        if autocommit.args[1] == path.to_str().unwrap() {
+            return Err(eyre!("Autocommit already exists on path"));
+        }
+    }
+
+    // Now we're ready to create the autocommit.
+    autocommits.push(new_cron_line(&path, frequency, repo_options.to_args())?);
+    write_autocommits(&autocommits)?;
+    Ok(())
+}
+
+/// The existing paths matching `path`, where any component can be a `*`
+/// pattern, e.g. `~/notes/*`. Paths without patterns are returned as is.
+pub fn expand_glob(path: &std::path::Path) -> Result<Vec<std::path::PathBuf>> {
+    if !path.to_string_lossy().contains('*') {
+        return Ok(vec![path.to_path_buf()]);
+    }
+    let mut matches = vec![std::path::PathBuf::new()];
+    for component in path.components() {
+        let component = component.as_os_str().to_string_lossy();
+        if !component.contains('*') {
+            matches.iter_mut().for_each(|m| m.push(component.as_ref()));
+            continue;
+        }
+        let mut next = Vec::new();
+        for base in &matches {
+            let dir = if base.as_os_str().is_empty() {
+                std::path::Path::new(".")
+            } else {
+                base.as_path()
+            };
+            let Ok(entries) = std::fs::read_dir(dir) else {
+                continue;
+            };
+            for entry in entries.filter_map(|e| e.ok()) {
+                let name = entry.file_name().to_string_lossy().to_string();
+                if !name.starts_with('.') && config::glob_match(&component, &name) {
+                    next.push(base.join(name));
+                }
+            }
+        }
+        next.sort();
+        matches = next;
+    }
+    // Only git repos, so `-p '~/src/*'` skips stray files and directories.
+    Ok(matches
+        .into_iter()
+        .filter(|m| m.join(".git").is_dir())
+        .collect())
+}
+
+/// The log redirection older crontab entries end with, which the shell has
+/// to see unquoted.
+const REDIRECTIONS: &[&str] = &[">>", "2>&1"];
+
+/// Quote `arg` for `/bin/sh`, leaving plain words and the redirections as
+/// they are so existing entries read the same.
+fn shell_quote(arg: &str) -> String {
+    let plain = !arg.is_empty()
+        && arg
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./:=@%+,".contains(c));
+    if plain || REDIRECTIONS.contains(&arg) {
+        return arg.to_string();
+    }
+    format!("'{}'", arg.replace('\'', r"'\''"))
+}
+
+/// Split a crontab line into words the way `/bin/sh` does, undoing
+/// `shell_quote`.
+fn shell_split(line: &str) -> Result<Vec<String>> {
+    let unterminated = || eyre!("Invalid cron line, unterminated quote in {}", line);
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => words.extend(word.take()),
+            '\'' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next().ok_or_else(unterminated)? {
+                        '\'' => break,
+                        c => word.push(c),
+                    }
+                }
+            }
+            '"' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next().ok_or_else(unterminated)? {
+                        '"' => break,
+                        '\\' => match chars.next().ok_or_else(unterminated)? {
+                            c @ ('"' | '\\' | '$' | '`') => word.push(c),
+                            c => {
+                                word.push('\\');
+                                word.push(c);
+                            }
+                        },
+                        c => word.push(c),
+                    }
+                }
+            }
+            '\\' => {
+                if let Some(c) = chars.next() {
+                    word.get_or_insert_with(String::new).push(c);
+                }
+            }
+            c => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    words.extend(word);
+    Ok(words)
+}
+
+/// The crontab entry running this binary on `path` as often as `frequency`
+/// says with the given `run` options.
+pub fn new_cron_line(
+    path: &std::path::Path,
+    frequency: Frequency,
+    options: Vec<String>,
+) -> Result<CronLine> {
+    let command_path = canonicalize(env::current_exe()?)?
+        .to_string_lossy()
+        .to_string();
+    debug!("Command path {}", command_path);
+
+    let mut args = vec![
+        "run".to_string(), // Run our binary.
+        path.to_str().unwrap().to_string(),
+    ];
+    args.extend(options);
+
+    Ok(CronLine::new(frequency.fields(), command_path, args))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Datelike, Weekday};
+
+    /// The weekdays of the next `count` runs of a 09:00 schedule on the
+    /// crontab days of the week `days`, from Monday 2024-01-01.
+    fn run_days(days: &str, count: usize) -> Vec<Weekday> {
+        let fields = ["0", "9", "*", "*", days].map(|field| field.to_string());
+        let line = CronLine::new(fields, "autocommit".to_string(), vec!["run".to_string()]);
+        let start = Local.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        line.schedule()
+            .unwrap()
+            .after(&start)
+            .take(count)
+            .map(|run| run.weekday())
+            .collect()
+    }
+
+    #[test]
+    fn schedule_numbers_days_like_crontab() {
+        assert_eq!(run_days("1", 2), [Weekday::Mon, Weekday::Mon]);
+        assert_eq!(run_days("6", 1), [Weekday::Sat]);
+        assert_eq!(
+            run_days("1-5", 5),
+            [
+                Weekday::Mon,
+                Weekday::Tue,
+                Weekday::Wed,
+                Weekday::Thu,
+                Weekday::Fri
+            ]
+        );
+        assert_eq!(
+            run_days("6,0", 4),
+            [Weekday::Sat, Weekday::Sun, Weekday::Sat, Weekday::Sun]
+        );
+    }
+
+    #[test]
+    fn schedule_accepts_both_sundays() {
+        assert_eq!(run_days("0", 2), [Weekday::Sun, Weekday::Sun]);
+        assert_eq!(run_days("7", 2), [Weekday::Sun, Weekday::Sun]);
+    }
+
+    #[test]
+    fn schedule_expands_ranges_ending_on_sunday() {
+        assert_eq!(
+            run_days("5-7", 4),
+            [Weekday::Fri, Weekday::Sat, Weekday::Sun, Weekday::Fri]
+        );
+        assert_eq!(run_days("0,7", 2), [Weekday::Sun, Weekday::Sun]);
+        assert_eq!(
+            run_days("1-7/2", 4),
+            [Weekday::Mon, Weekday::Wed, Weekday::Fri, Weekday::Sun]
+        );
+    }
+
+    #[test]
+    fn cron_line_round_trips_shell_characters() {
+        let options = RepoOptions {
+            pathspecs: vec!["src/(generated) *.rs".to_string(), "it's".to_string()],
+            ..Default::default()
+        };
+        let mut args = vec!["run".to_string(), "/home/me/my repo".to_string()];
+        args.extend(options.to_args());
+        let line = CronLine::new(
+            Frequency::Minutes(5).fields(),
+            "/usr/local/bin/autocommit".to_string(),
+            args.clone(),
+        );
+        let parsed = CronLine::parse(&line.to_string()).unwrap();
+        assert_eq!(parsed.command, "/usr/local/bin/autocommit");
+        assert_eq!(parsed.args, args);
+        assert_eq!(parsed.repo_path(), "/home/me/my repo");
+    }
+
+    #[test]
+    fn cron_line_round_trips_webhook_urls() {
+        let webhook = "https://hooks.example.com/notify?repo=my%20repo&token=abc";
+        let options = RepoOptions {
+            webhook: Some(webhook.to_string()),
+            ..Default::default()
+        };
+        let mut args = vec!["run".to_string(), "/home/me/repo".to_string()];
+        args.extend(options.to_args());
+        let line = CronLine::new(
+            Frequency::Minutes(5).fields(),
+            "/usr/local/bin/autocommit".to_string(),
+            args.clone(),
+        );
+        let crontab = line.to_string();
+        let quoted = "'https://hooks.example.com/notify?repo=my\\%20repo&token=abc'";
+        assert!(crontab.contains(quoted));
+        assert_eq!(CronLine::parse(&crontab).unwrap().args, args);
+    }
+
+    #[test]
+    fn shell_command_keeps_arguments_whole() {
+        let args = ["%s\\n", "src/(generated) *.rs", "it's", "$HOME"];
+        let line = CronLine::new(
+            Frequency::Minutes(5).fields(),
+            "printf".to_string(),
+            args.iter().map(|arg| arg.to_string()).collect(),
+        );
+        let output = Command::new("sh")
+            .arg("-c")
+            .arg(line.shell_command())
+            .output()
+            .unwrap();
+        assert_eq!(
+            String::from_utf8_lossy(&output.stdout),
+            "src/(generated) *.rs\nit's\n$HOME\n"
+        );
+    }
+
+    #[test]
+    fn parse_time_reads_hours_and_minutes() {
+        assert_eq!(
+            parse_time("18:05"),
+            Ok(Frequency::Daily {
+                hour: 18,
+                minute: 5
+            })
+        );
+        assert_eq!(
+            parse_time("00:00"),
+            Ok(Frequency::Daily { hour: 0, minute: 0 })
+        );
+        assert!(parse_time("24:00").is_err());
+        assert!(parse_time("6pm").is_err());
+    }
+
+    #[test]
+    fn format_dirty_secs_picks_units() {
+        assert_eq!(format_dirty_secs(0), "0s");
+        assert_eq!(format_dirty_secs(59), "59s");
+        assert_eq!(format_dirty_secs(60), "1m");
+        assert_eq!(format_dirty_secs(3599), "59m");
+        assert_eq!(format_dirty_secs(3600), "1h");
+        assert_eq!(format_dirty_secs(3659), "1h");
+        assert_eq!(format_dirty_secs(7800), "2h10m");
+    }
+}
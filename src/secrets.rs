@@ -0,0 +1,101 @@
+use crate::config::{config_dir, Config};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use chacha20poly1305::aead::{Aead, KeyInit, OsRng};
+use chacha20poly1305::{AeadCore, ChaCha20Poly1305, Key, Nonce};
+use color_eyre::{eyre::eyre, Result};
+
+/// Encrypted values in the config are written as `enc:<base64>`, the nonce
+/// followed by the ciphertext.
+const PREFIX: &str = "enc:";
+const NONCE_LEN: usize = 12;
+
+/// The key file, `AUTOCOMMIT_KEY_FILE` or `~/.config/autocommit/secret.key`.
+fn key_path() -> Result<std::path::PathBuf> {
+    match std::env::var_os("AUTOCOMMIT_KEY_FILE") {
+        Some(path) if !path.is_empty() => Ok(path.into()),
+        _ => Ok(config_dir()?.join("secret.key")),
+    }
+}
+
+fn read_key(path: &std::path::Path) -> Result<ChaCha20Poly1305> {
+    let encoded = std::fs::read_to_string(path)
+        .map_err(|e| eyre!("Could not read the key file {}: {}", path.display(), e))?;
+    let key = STANDARD.decode(encoded.trim())?;
+    if key.len() != 32 {
+        return Err(eyre!("Invalid key file {}", path.display()));
+    }
+    Ok(ChaCha20Poly1305::new(Key::from_slice(&key)))
+}
+
+/// The cipher for the key file, generating the key file if there is none.
+fn cipher_or_generate() -> Result<ChaCha20Poly1305> {
+    let path = key_path()?;
+    if !path.exists() {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let key = ChaCha20Poly1305::generate_key(&mut OsRng);
+        std::fs::write(&path, STANDARD.encode(key) + "\n")?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600))?;
+        }
+        eprintln!("Generated a new key file at {}", path.display());
+    }
+    read_key(&path)
+}
+
+/// Encrypt `value` for the `[secrets]` table of the config.
+pub fn encrypt(value: &str) -> Result<String> {
+    let cipher = cipher_or_generate()?;
+    let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let ciphertext = cipher
+        .encrypt(&nonce, value.as_bytes())
+        .map_err(|_| eyre!("Could not encrypt the secret"))?;
+    let mut sealed = nonce.to_vec();
+    sealed.extend(ciphertext);
+    Ok(format!("{}{}", PREFIX, STANDARD.encode(sealed)))
+}
+
+fn decrypt(cipher: &ChaCha20Poly1305, name: &str, value: &str) -> Result<String> {
+    let Some(encoded) = value.strip_prefix(PREFIX) else {
+        return Err(eyre!(
+            "Secret {} is not encrypted, use `autocommit secret encrypt`",
+            name
+        ));
+    };
+    let sealed = STANDARD.decode(encoded)?;
+    if sealed.len() < NONCE_LEN {
+        return Err(eyre!("Secret {} is corrupt", name));
+    }
+    let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+    let plaintext = cipher
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|_| {
+            eyre!(
+                "Could not decrypt secret {}, was it encrypted with another key?",
+                name
+            )
+        })?;
+    Ok(String::from_utf8(plaintext)?)
+}
+
+/// Decrypt the config's `[secrets]` into environment variables of the same
+/// name. Variables already set in the environment are left alone.
+pub fn load(config: &Config) -> Result<()> {
+    let pending = config
+        .secrets
+        .iter()
+        .filter(|(name, _)| std::env::var_os(name).is_none())
+        .collect::<Vec<_>>();
+    if pending.is_empty() {
+        return Ok(());
+    }
+    let cipher = read_key(&key_path()?)?;
+    for (name, value) in pending {
+        std::env::set_var(name, decrypt(&cipher, name, value)?);
+    }
+    Ok(())
+}
@@ -0,0 +1,213 @@
+use crate::{
+    capped_diff, dirty_for, generate_message, push_refspec, push_threshold_reached,
+    run_checked_command_in_dir, run_checked_command_with_timeout, run_repo_hook, working_tree,
+    Generation, MessageGenerator, PushPolicy, RunOptions, RunOutcome,
+};
+use color_eyre::{eyre::eyre, Result};
+use tracing::{error, info, info_span, warn, Instrument};
+
+/// The checked-out branch and the ref its snapshots are committed to.
+pub fn snapshot_ref(repo_path: &std::path::PathBuf) -> Result<(String, String)> {
+    let branch = run_checked_command_in_dir(repo_path, "git", &["symbolic-ref", "--short", "HEAD"])
+        .map_err(|_| eyre!("Snapshot branches need a checked-out branch, HEAD is detached"))?
+        .trim()
+        .to_string();
+    let snapshot = format!("refs/heads/autocommit/{}", branch);
+    Ok((branch, snapshot))
+}
+
+/// Let the repo's `commit-msg` hook check (and possibly rewrite) the message.
+/// `commit-tree` skips hooks, and `pre-commit` hooks look at the real index
+/// that snapshots leave alone, so this is the only one that applies.
+fn commit_msg_hook(repo_path: &std::path::PathBuf, message: String) -> Result<String> {
+    let path = repo_path.join(".git").join("AUTOCOMMIT_EDITMSG");
+    std::fs::write(&path, message)?;
+    let result = run_repo_hook(repo_path, "commit-msg", &[&path.to_string_lossy()])
+        .and_then(|_| std::fs::read_to_string(&path).map_err(Into::into));
+    let _ = std::fs::remove_file(&path);
+    result
+}
+
+/// Commit the working tree to `autocommit/<branch>` and push it there, without
+/// changing the checked-out branch, its index or its working tree.
+pub async fn run(
+    repo_path: &std::path::PathBuf,
+    options: &RunOptions,
+    generator: &dyn MessageGenerator,
+) -> Result<RunOutcome> {
+    let (branch, snapshot) = snapshot_ref(repo_path)?;
+
+    let tree = info_span!("status", phase = "status")
+        .in_scope(|| working_tree(repo_path, &options.repo.pathspecs))?;
+    let previous = run_checked_command_in_dir(
+        repo_path,
+        "git",
+        &["rev-parse", "--verify", "-q", &snapshot],
+    )
+    .ok()
+    .map(|sha| sha.trim().to_string())
+    .filter(|sha| !sha.is_empty());
+    let head = run_checked_command_in_dir(repo_path, "git", &["rev-parse", "HEAD"])?
+        .trim()
+        .to_string();
+    let base = previous.clone().unwrap_or_else(|| head.clone());
+    let base_tree = run_checked_command_in_dir(
+        repo_path,
+        "git",
+        &["rev-parse", &format!("{}^{{tree}}", base)],
+    )?;
+    if base_tree.trim() == tree {
+        return Ok(RunOutcome::NothingToCommit);
+    }
+
+    let diff = info_span!("diff", phase = "diff")
+        .in_scope(|| capped_diff(repo_path, &["diff", &base, &tree]))?;
+    let (message, generation) = match &options.message {
+        Some(message) => (message.clone(), Generation::default()),
+        None => generate_message(generator, repo_path, &diff).await?,
+    };
+    let message =
+        crate::trailers::append(repo_path, &message, &crate::config::Config::load()?.commit)?;
+    let message = if options.repo.no_verify || options.dry_run {
+        message
+    } else {
+        commit_msg_hook(repo_path, message)?
+    };
+    info!("commit message: {}", message);
+
+    let remote = push_refspec(repo_path)
+        .map(|refspec| refspec.remote)
+        .unwrap_or_else(|| "origin".to_string());
+
+    if options.dry_run {
+        let files =
+            run_checked_command_in_dir(repo_path, "git", &["diff", "--name-status", &base, &tree])?;
+        let branch = snapshot.trim_start_matches("refs/heads/");
+        return Ok(RunOutcome::DryRun {
+            branch: Some(branch.to_string()),
+            files: files.lines().map(|line| line.to_string()).collect(),
+            message,
+            groups: Vec::new(),
+            push_target: Some(format!("{}/{}", remote, branch)),
+            push_policy: None,
+        });
+    }
+
+    let commit_span = info_span!("commit", phase = "commit").entered();
+    // Keep the real branch's history reachable from the snapshots by merging
+    // it in whenever it moved on since the last snapshot.
+    let mut args = vec![
+        "commit-tree",
+        tree.as_str(),
+        "-p",
+        base.as_str(),
+        "-m",
+        message.as_str(),
+    ];
+    if previous.is_some()
+        && run_checked_command_in_dir(
+            repo_path,
+            "git",
+            &["merge-base", "--is-ancestor", &head, &base],
+        )
+        .is_err()
+    {
+        args.extend(["-p", head.as_str()]);
+    }
+    let sha = run_checked_command_in_dir(repo_path, "git", &args)?
+        .trim()
+        .to_string();
+    let mut update_ref = vec![
+        "update-ref",
+        "-m",
+        "autocommit snapshot",
+        snapshot.as_str(),
+        sha.as_str(),
+    ];
+    if let Some(previous) = &previous {
+        update_ref.push(previous.as_str());
+    }
+    run_checked_command_in_dir(repo_path, "git", &update_ref)?;
+    let files: Vec<String> =
+        run_checked_command_in_dir(repo_path, "git", &["diff", "--name-only", &base, &sha])?
+            .lines()
+            .map(|f| f.to_string())
+            .collect();
+    let dirty_for = dirty_for(repo_path, &files, &base);
+    if generation.model.is_some() {
+        crate::notes::add(repo_path, &sha, &generation)?;
+    }
+    commit_span.exit();
+
+    let short_ref = snapshot.trim_start_matches("refs/heads/").to_string();
+    let remote_ref = format!("refs/remotes/{}/{}", remote, short_ref);
+    let pushed_before = run_checked_command_in_dir(
+        repo_path,
+        "git",
+        &["rev-parse", "--verify", "-q", &remote_ref],
+    )
+    .is_ok();
+    let push = match options.repo.push_policy() {
+        PushPolicy::Never => false,
+        // Nothing to batch against until the snapshot branch exists remotely.
+        _ if !pushed_before => true,
+        policy => {
+            push_threshold_reached(repo_path, policy, &format!("{}..{}", remote_ref, snapshot))?
+        }
+    };
+    let mut push_error = None;
+    let push_duration = if push {
+        let _push_span = info_span!("push", phase = "push").entered();
+        let push_start = std::time::Instant::now();
+        match run_checked_command_with_timeout(
+            repo_path,
+            "git",
+            &["push", &remote, &format!("{}:{}", snapshot, snapshot)],
+            crate::config::Config::load()?.timeouts.push(),
+        ) {
+            Ok(_) => Some(push_start.elapsed()),
+            Err(e) => {
+                error!(error = %format!("{:#}", e), "Push failed, the snapshot stays local");
+                push_error = Some(format!("{:#}", e));
+                None
+            }
+        }
+    } else {
+        info!(
+            "Not pushing yet (push policy: {})",
+            options.repo.push_policy()
+        );
+        None
+    };
+
+    let mut generation = generation;
+    if options.repo.open_pr && push_duration.is_some() {
+        // The snapshot is committed and pushed either way, the pull request
+        // is tried again after the next push.
+        match crate::pr::open_or_update(repo_path, &remote, &short_ref, &branch, generator)
+            .instrument(info_span!("pull_request", phase = "pull_request"))
+            .await
+        {
+            Ok(usage) => {
+                generation.prompt_tokens += usage.prompt_tokens;
+                generation.completion_tokens += usage.completion_tokens;
+            }
+            Err(e) => warn!(error = %format!("{:#}", e), "Opening the pull request failed"),
+        }
+    }
+
+    Ok(RunOutcome::Committed {
+        tree: Some(tree),
+        split_commits: Vec::new(),
+        sha,
+        branch: short_ref,
+        message,
+        files,
+        amended: false,
+        pushed: push_duration.is_some(),
+        push_duration,
+        push_error,
+        dirty_for,
+        generation,
+    })
+}
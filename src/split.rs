@@ -0,0 +1,156 @@
+use crate::{
+    capped_diff, config, generate_message, notes, run_checked_command_in_dir, run_command_in_dir,
+    trailers, MessageGenerator,
+};
+use color_eyre::Result;
+use std::collections::HashSet;
+use tracing::{info, info_span, Instrument};
+
+/// Sort the changed tracked files (those matching `pathspecs`, or all of
+/// them) into groups that are committed separately: one per `path_groups`
+/// entry if there are any, otherwise as the generator sees fit. `None` if
+/// there is only one group.
+pub async fn plan(
+    repo_path: &std::path::PathBuf,
+    generator: &dyn MessageGenerator,
+    pathspecs: &[String],
+    path_groups: &[String],
+    diff: &str,
+) -> Result<Option<Vec<Vec<String>>>> {
+    let mut args = vec!["diff", "--name-only", "HEAD", "--"];
+    args.extend(pathspecs.iter().map(|p| p.as_str()));
+    let files: Vec<String> = run_command_in_dir(repo_path, "git", &args)?
+        .lines()
+        .filter(|f| !f.is_empty())
+        .map(|f| f.to_string())
+        .collect();
+    if files.len() < 2 {
+        return Ok(None);
+    }
+    let groups = if path_groups.is_empty() {
+        generator
+            .group(&files, diff)
+            .instrument(info_span!("group_files", phase = "group_files"))
+            .await?
+    } else {
+        by_path(&files, path_groups)
+    };
+    let groups = normalize(&files, groups);
+    info!("Split the changes into {} commits", groups.len());
+    Ok((groups.len() > 1).then_some(groups))
+}
+
+/// Group files by the directory they are under, in the order of `prefixes`.
+fn by_path(files: &[String], prefixes: &[String]) -> Vec<Vec<String>> {
+    prefixes
+        .iter()
+        .map(|prefix| {
+            let prefix = prefix.trim_end_matches('/');
+            files
+                .iter()
+                .filter(|f| {
+                    f.strip_prefix(prefix)
+                        .map_or(false, |rest| rest.is_empty() || rest.starts_with('/'))
+                })
+                .cloned()
+                .collect()
+        })
+        .collect()
+}
+
+/// Keep each changed file in exactly one group (the first it appears in),
+/// dropping files the model made up and putting the ones no group claimed into
+/// a group of their own.
+fn normalize(files: &[String], groups: Vec<Vec<String>>) -> Vec<Vec<String>> {
+    let mut seen = HashSet::new();
+    let mut normalized: Vec<Vec<String>> = groups
+        .into_iter()
+        .map(|group| {
+            group
+                .into_iter()
+                .filter(|f| files.contains(f) && seen.insert(f.clone()))
+                .collect::<Vec<_>>()
+        })
+        .filter(|group| !group.is_empty())
+        .collect();
+    let rest: Vec<String> = files
+        .iter()
+        .filter(|f| !seen.contains(*f))
+        .cloned()
+        .collect();
+    if !rest.is_empty() {
+        normalized.push(rest);
+    }
+    normalized
+}
+
+/// Commit each group of files separately with its own generated message.
+/// Returns the commits, oldest first.
+pub async fn commit_groups(
+    repo_path: &std::path::PathBuf,
+    generator: &dyn MessageGenerator,
+    groups: &[Vec<String>],
+    no_verify: bool,
+) -> Result<Vec<String>> {
+    let commit_config = config::Config::load()?.commit;
+    let mut commits = Vec::new();
+    for group in groups {
+        let mut args = vec!["diff", "HEAD", "--"];
+        args.extend(group.iter().map(|f| f.as_str()));
+        let diff = capped_diff(repo_path, &args)?;
+        let (message, generation) = generate_message(generator, repo_path, &diff).await?;
+        let message = trailers::append(repo_path, &message, &commit_config)?;
+        info!("commit message: {}", message);
+
+        let mut args = vec!["commit", "-m", &message];
+        if no_verify {
+            args.push("--no-verify");
+        }
+        args.push("--");
+        args.extend(group.iter().map(|f| f.as_str()));
+        run_checked_command_in_dir(repo_path, "git", &args)?;
+        let sha = run_command_in_dir(repo_path, "git", &["rev-parse", "HEAD"])?
+            .trim()
+            .to_string();
+        if generation.model.is_some() {
+            notes::add(repo_path, &sha, &generation)?;
+        }
+        commits.push(sha);
+    }
+    Ok(commits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(items: &[&str]) -> Vec<String> {
+        items.iter().map(|item| item.to_string()).collect()
+    }
+
+    #[test]
+    fn normalize_keeps_each_file_once() {
+        let files = strings(&["a", "b", "c", "d"]);
+        let groups = vec![
+            strings(&["a", "made-up"]),
+            strings(&["a", "b"]),
+            strings(&["made-up"]),
+            strings(&["c"]),
+        ];
+        assert_eq!(
+            normalize(&files, groups),
+            vec![
+                strings(&["a"]),
+                strings(&["b"]),
+                strings(&["c"]),
+                strings(&["d"])
+            ]
+        );
+    }
+
+    #[test]
+    fn normalize_without_groups_is_one_group() {
+        let files = strings(&["a", "b"]);
+        assert_eq!(normalize(&files, Vec::new()), vec![files.clone()]);
+    }
+}
@@ -0,0 +1,180 @@
+use crate::{
+    capped_diff, config, generate_message, is_pushed, notes, push_branch,
+    run_checked_command_in_dir, run_command_in_dir, state, trailers, MessageGenerator,
+};
+use chrono::{DateTime, Local, NaiveDate, TimeZone};
+use color_eyre::{eyre::eyre, Result};
+use tracing::{info, warn};
+
+/// Parse `--since`: `today`, `yesterday` or a `YYYY-MM-DD` date, as the start
+/// of that day in local time.
+pub fn parse_since(since: &str) -> Result<DateTime<Local>> {
+    let today = Local::now().date_naive();
+    let day = match since {
+        "today" => today,
+        "yesterday" => today
+            .pred_opt()
+            .ok_or_else(|| eyre!("Invalid date {}", since))?,
+        date => NaiveDate::parse_from_str(date, "%Y-%m-%d").map_err(|_| {
+            eyre!(
+                "Invalid --since {}, expected today, yesterday or YYYY-MM-DD",
+                date
+            )
+        })?,
+    };
+    start_of_day(day)
+}
+
+pub fn start_of_day(day: NaiveDate) -> Result<DateTime<Local>> {
+    day.and_hms_opt(0, 0, 0)
+        .and_then(|midnight| Local.from_local_datetime(&midnight).earliest())
+        .ok_or_else(|| eyre!("Invalid date {}", day))
+}
+
+/// The consecutive autocommits leading up to HEAD that were made after
+/// `since`, newest first. Stops at merges, since squashing would flatten them.
+pub(crate) fn consecutive_autocommits(
+    repo_path: &std::path::PathBuf,
+    autocommits: &[String],
+    commit_config: &config::CommitConfig,
+    since: DateTime<Local>,
+) -> Result<Vec<String>> {
+    // Records are separated by \x1e, the message follows \x1f.
+    let log = run_command_in_dir(
+        repo_path,
+        "git",
+        &["log", "--first-parent", "--format=%H %at %P%x1f%B%x1e"],
+    )?;
+    let mut commits = Vec::new();
+    for record in log.split('\x1e') {
+        let Some((line, message)) = record.trim_start().split_once('\x1f') else {
+            break;
+        };
+        let mut parts = line.split_whitespace();
+        let (Some(sha), Some(authored_at)) = (parts.next(), parts.next()) else {
+            break;
+        };
+        let authored_at: i64 = authored_at.parse()?;
+        let single_parent = parts.count() == 1;
+        if !single_parent
+            || authored_at < since.timestamp()
+            || !(autocommits.iter().any(|a| a == sha)
+                || trailers::is_marked(message, commit_config))
+        {
+            break;
+        }
+        commits.push(sha.to_string());
+    }
+    Ok(commits)
+}
+
+/// The autocommits `squash` would collapse, newest first.
+fn squashable(repo_path: &std::path::PathBuf, since: DateTime<Local>) -> Result<Vec<String>> {
+    let autocommits = state::State::load()?
+        .get(repo_path)
+        .map(|repo_state| repo_state.autocommits.clone())
+        .unwrap_or_default();
+    let commit_config = config::Config::load()?.commit;
+    consecutive_autocommits(repo_path, &autocommits, &commit_config, since)
+}
+
+/// Collapse the consecutive autocommits made since `since` into a single
+/// commit with a message `generator` writes for their combined diff. Returns
+/// the new commit, or `None` if there was nothing to squash.
+pub async fn squash(
+    repo_path: &std::path::PathBuf,
+    since: DateTime<Local>,
+    force_with_lease: bool,
+    generator: &dyn MessageGenerator,
+) -> Result<Option<String>> {
+    let config = config::Config::load()?;
+    let commit_config = config.commit;
+    let commits = squashable(repo_path, since)?;
+    if commits.len() < 2 {
+        info!("Nothing to squash since {}", since.format("%Y-%m-%d %H:%M"));
+        return Ok(None);
+    }
+    let oldest = commits.last().unwrap();
+    let base = format!("{}~1", oldest);
+
+    let pushed = is_pushed(repo_path, oldest)?;
+    if pushed && !force_with_lease {
+        return Err(eyre!(
+            "Some of the autocommits to squash were already pushed, use --force-with-lease to squash and force push them"
+        ));
+    }
+
+    info!("Squashing {} autocommits", commits.len());
+    let diff = capped_diff(repo_path, &["diff", &base, "HEAD"])?;
+    let (message, generation) = generate_message(generator, repo_path, &diff).await?;
+    let message = trailers::append(repo_path, &message, &commit_config)?;
+
+    // Build the commit from HEAD's tree without touching the index, so
+    // anything the user staged stays staged and out of the squash.
+    let head = run_checked_command_in_dir(repo_path, "git", &["rev-parse", "HEAD"])?
+        .trim()
+        .to_string();
+    let sha = run_checked_command_in_dir(
+        repo_path,
+        "git",
+        &["commit-tree", "HEAD^{tree}", "-p", &base, "-m", &message],
+    )?
+    .trim()
+    .to_string();
+    run_checked_command_in_dir(
+        repo_path,
+        "git",
+        &["update-ref", "-m", "autocommit squash", "HEAD", &sha, &head],
+    )?;
+    if generation.model.is_some() {
+        notes::add(repo_path, &sha, &generation)?;
+    }
+
+    if pushed {
+        push_branch(repo_path, true, config.timeouts.push())?;
+    }
+
+    state::State::update(|state| {
+        let repo_state = state.repo(repo_path);
+        repo_state.autocommits.retain(|a| !commits.contains(a));
+        repo_state.autocommits.push(sha.clone());
+        repo_state.last_commit = Some(sha.clone());
+        repo_state.last_message = Some(message);
+        Ok(())
+    })?;
+
+    Ok(Some(sha))
+}
+
+/// For `--squash-daily`: once HEAD is an autocommit from an earlier day,
+/// squash that day's autocommits.
+pub async fn squash_previous_day(
+    repo_path: &std::path::PathBuf,
+    force_with_lease: bool,
+    generator: &dyn MessageGenerator,
+) -> Result<()> {
+    let authored_at = run_command_in_dir(repo_path, "git", &["log", "-1", "--format=%at"])?;
+    let Some(authored_at) = authored_at
+        .trim()
+        .parse()
+        .ok()
+        .and_then(|at| Local.timestamp_opt(at, 0).single())
+    else {
+        return Ok(());
+    };
+    if authored_at >= start_of_day(Local::now().date_naive())? {
+        return Ok(());
+    }
+    let since = start_of_day(authored_at.date_naive())?;
+    // Failing here would stop every later run, so leave pushed days alone.
+    if !force_with_lease {
+        if let Some(oldest) = squashable(repo_path, since)?.last() {
+            if is_pushed(repo_path, oldest)? {
+                warn!("Not squashing autocommits that were already pushed, pass --force-with-lease to squash them");
+                return Ok(());
+            }
+        }
+    }
+    squash(repo_path, since, force_with_lease, generator).await?;
+    Ok(())
+}
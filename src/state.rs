@@ -0,0 +1,194 @@
+use crate::config::state_dir;
+use crate::discover::DiscoverRoot;
+use crate::lock::RepoLock;
+use crate::{failure_message, RunOutcome};
+use color_eyre::{eyre::eyre, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+/// How long to wait for another process to finish updating the state file.
+const LOCK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// What autocommit remembers between runs, stored in
+/// `~/.local/state/autocommit/state.json`.
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct State {
+    /// Keyed by canonical repo path.
+    repos: BTreeMap<String, RepoState>,
+    /// Directories watched for new repos, see `create --discover --sync`.
+    pub discover: Vec<DiscoverRoot>,
+    /// Unix timestamp of the last scan of `discover`.
+    pub last_discover_at: Option<i64>,
+}
+
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct RepoState {
+    pub consecutive_failures: u32,
+    pub last_error: Option<String>,
+    pub runs: u64,
+    pub commits: u64,
+    pub failures: u64,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    /// Unix timestamp of the last run.
+    pub last_run_at: Option<i64>,
+    /// Unix timestamp of the last run that didn't fail, to find missed
+    /// schedules, see `autocommit catchup`.
+    pub last_success_at: Option<i64>,
+    pub last_result: Option<String>,
+    pub last_commit: Option<String>,
+    pub last_message: Option<String>,
+    /// The working tree the last autocommit was made from, to skip runs
+    /// that would commit the same changes again.
+    pub last_tree: Option<String>,
+    /// Commits made by autocommit, oldest first, so they can be told apart
+    /// from manual commits.
+    pub autocommits: Vec<String>,
+    /// How long changes sat uncommitted before each autocommit, summed over
+    /// `dirty_samples` commits, to tune the schedule to.
+    pub dirty_secs_total: u64,
+    pub dirty_samples: u64,
+    pub last_dirty_secs: Option<u64>,
+}
+
+/// How many autocommits to remember per repo.
+const MAX_AUTOCOMMITS: usize = 1000;
+
+impl RepoState {
+    /// Update the counters and failure streak with the result of a run.
+    pub fn record(&mut self, result: &Result<RunOutcome>) {
+        self.runs += 1;
+        let now = chrono::Utc::now().timestamp();
+        self.last_run_at = Some(now);
+        self.last_result = Some(match result {
+            Ok(outcome) => outcome.name().to_string(),
+            Err(_) => "error".to_string(),
+        });
+        if let Ok(RunOutcome::Committed {
+            sha,
+            message,
+            generation,
+            amended,
+            tree,
+            split_commits,
+            dirty_for,
+            ..
+        }) = result
+        {
+            if *amended {
+                // The amended commit replaces the previous autocommit.
+                self.autocommits.pop();
+            }
+            self.autocommits.extend(split_commits.iter().cloned());
+            self.autocommits.push(sha.clone());
+            if self.autocommits.len() > MAX_AUTOCOMMITS {
+                let excess = self.autocommits.len() - MAX_AUTOCOMMITS;
+                self.autocommits.drain(..excess);
+            }
+            self.commits += 1 + split_commits.len() as u64;
+            self.prompt_tokens += generation.prompt_tokens;
+            self.completion_tokens += generation.completion_tokens;
+            self.last_commit = Some(sha.clone());
+            self.last_message = Some(message.clone());
+            self.last_tree = tree.clone();
+            if let Some(dirty_for) = dirty_for {
+                self.dirty_secs_total += dirty_for.as_secs();
+                self.dirty_samples += 1;
+                self.last_dirty_secs = Some(dirty_for.as_secs());
+            }
+        }
+        // A commit whose push failed was still made, so it doesn't count as
+        // missed by `catchup`, but it does count towards the failure streak.
+        if result.is_ok() {
+            self.last_success_at = Some(now);
+        }
+        match failure_message(result) {
+            None => {
+                self.consecutive_failures = 0;
+                self.last_error = None;
+            }
+            Some(error) => {
+                self.failures += 1;
+                self.consecutive_failures += 1;
+                self.last_error = Some(error);
+            }
+        }
+    }
+
+    /// The average time changes sat uncommitted before an autocommit.
+    pub fn avg_dirty_secs(&self) -> Option<u64> {
+        (self.dirty_samples > 0).then(|| self.dirty_secs_total / self.dirty_samples)
+    }
+}
+
+impl State {
+    pub fn path() -> Result<std::path::PathBuf> {
+        Ok(state_dir()?.join("state.json"))
+    }
+
+    pub fn load() -> Result<Self> {
+        let path = Self::path()?;
+        if !path.exists() {
+            return Ok(State::default());
+        }
+        let contents = std::fs::read_to_string(&path)?;
+        serde_json::from_str(&contents)
+            .map_err(|e| eyre!("Invalid state file {}: {}", path.display(), e))
+    }
+
+    /// Load the state file, apply `change` and save it, holding the state
+    /// lock throughout so concurrent runs don't drop each other's updates.
+    pub fn update<T>(change: impl FnOnce(&mut State) -> Result<T>) -> Result<T> {
+        let path = Self::path()?;
+        std::fs::create_dir_all(state_dir()?)?;
+        let deadline = Instant::now() + LOCK_TIMEOUT;
+        let _lock = loop {
+            if let Some(lock) = RepoLock::acquire_file(path.with_extension("lock"))? {
+                break lock;
+            }
+            if Instant::now() >= deadline {
+                return Err(eyre!("Timed out waiting for the state file lock"));
+            }
+            std::thread::sleep(Duration::from_millis(50));
+        };
+        let mut state = Self::load()?;
+        let result = change(&mut state)?;
+        state.save()?;
+        Ok(result)
+    }
+
+    /// Write the state file atomically, so concurrent runs never see it half
+    /// written.
+    fn save(&self) -> Result<()> {
+        let path = Self::path()?;
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let tmp = path.with_extension(format!("json.{}", std::process::id()));
+        std::fs::write(&tmp, serde_json::to_string_pretty(self)?)?;
+        std::fs::rename(&tmp, &path)?;
+        Ok(())
+    }
+
+    pub fn get(&self, repo_path: &std::path::Path) -> Option<&RepoState> {
+        self.repos.get(repo_path.to_string_lossy().as_ref())
+    }
+
+    /// The repos there is state for.
+    pub fn repo_paths(&self) -> impl Iterator<Item = &String> {
+        self.repos.keys()
+    }
+
+    pub fn remove(&mut self, repo_path: &std::path::Path) -> Option<RepoState> {
+        self.repos.remove(repo_path.to_string_lossy().as_ref())
+    }
+
+    pub fn repo(&mut self, repo_path: &std::path::Path) -> &mut RepoState {
+        self.repos
+            .entry(repo_path.to_string_lossy().to_string())
+            .or_default()
+    }
+}
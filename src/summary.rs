@@ -0,0 +1,59 @@
+use crate::{
+    capped_diff, config, run_checked_command_in_dir, squash, state, Generation, MessageGenerator,
+};
+use chrono::{DateTime, Local};
+use color_eyre::{eyre::eyre, Result};
+use tracing::{info, info_span, Instrument};
+
+/// Where the autocommits leading up to HEAD start, the parent of the oldest
+/// one. `None` if HEAD isn't an autocommit.
+pub fn autocommits_base(repo_path: &std::path::PathBuf) -> Result<Option<String>> {
+    let autocommits = state::State::load()?
+        .get(repo_path)
+        .map(|repo_state| repo_state.autocommits.clone())
+        .unwrap_or_default();
+    let commits = squash::consecutive_autocommits(
+        repo_path,
+        &autocommits,
+        &config::Config::load()?.commit,
+        DateTime::<Local>::from(std::time::UNIX_EPOCH),
+    )?;
+    Ok(commits.last().map(|oldest| format!("{}~1", oldest)))
+}
+
+/// Summarize what changed since `since` (a revision, by default where the
+/// autocommits leading up to HEAD start) up to the working tree, from the
+/// commit messages and the combined diff. Nothing in the repo is changed.
+pub async fn summarize(
+    repo_path: &std::path::PathBuf,
+    generator: &dyn MessageGenerator,
+    since: Option<&str>,
+) -> Result<(String, Generation)> {
+    if !repo_path.join(".git").is_dir() {
+        return Err(eyre!("Path is not a git repo"));
+    }
+    let base = match since {
+        Some(rev) => rev.to_string(),
+        None => autocommits_base(repo_path)?
+            .ok_or_else(|| eyre!("HEAD is not an autocommit, pass a revision to --since"))?,
+    };
+    let log = run_checked_command_in_dir(
+        repo_path,
+        "git",
+        &[
+            "log",
+            "--no-merges",
+            "--format=- %s",
+            &format!("{}..HEAD", base),
+        ],
+    )?;
+    let diff = capped_diff(repo_path, &["diff", &base])?;
+    if log.trim().is_empty() && diff.trim().is_empty() {
+        return Err(eyre!("Nothing changed since {}", base));
+    }
+    info!("Summarizing changes since {}", base);
+    generator
+        .summarize(&log, &diff)
+        .instrument(info_span!("summarize", phase = "summarize"))
+        .await
+}
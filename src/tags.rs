@@ -0,0 +1,84 @@
+use crate::squash::start_of_day;
+use crate::{
+    config, push_refspec, push_target, run_checked_command_in_dir,
+    run_checked_command_with_timeout, run_command_in_dir,
+};
+use chrono::{Local, TimeZone};
+use color_eyre::Result;
+use tracing::{debug, info, warn};
+
+/// Daily snapshot tags are named `autocommit/<YYYY-MM-DD>`.
+pub const TAG_PREFIX: &str = "autocommit/";
+
+/// For `--tag-daily`: tag the last commit on `rev` from before today with the
+/// day it was made, unless that day is tagged already, and with `push` push
+/// the tag if the remote doesn't have it yet. Returns the new tag.
+pub fn tag_previous_day(
+    repo_path: &std::path::PathBuf,
+    rev: &str,
+    push: bool,
+) -> Result<Option<String>> {
+    let midnight = start_of_day(Local::now().date_naive())?;
+    let last = run_command_in_dir(
+        repo_path,
+        "git",
+        &[
+            "log",
+            "-1",
+            "--first-parent",
+            &format!("--before={}", midnight.to_rfc3339()),
+            "--format=%H %ct",
+            rev,
+        ],
+    )?;
+    let Some((sha, committed_at)) = last.trim().split_once(' ') else {
+        return Ok(None);
+    };
+    let Some(day) = committed_at
+        .parse()
+        .ok()
+        .and_then(|at| Local.timestamp_opt(at, 0).single())
+    else {
+        return Ok(None);
+    };
+    let tag = format!("{}{}", TAG_PREFIX, day.format("%Y-%m-%d"));
+    let tag_ref = format!("refs/tags/{}", tag);
+    let exists =
+        run_checked_command_in_dir(repo_path, "git", &["rev-parse", "--verify", "-q", &tag_ref])
+            .is_ok();
+    if !exists {
+        run_checked_command_in_dir(repo_path, "git", &["tag", &tag, sha])?;
+        info!("Tagged {} as {}", sha, tag);
+    }
+
+    // A failed push (e.g. offline) shouldn't fail the run, the next run
+    // pushes the tag once the remote is reachable.
+    if push {
+        if let Err(e) = push_tag(repo_path, &tag_ref) {
+            warn!("Could not push {}: {:#}", tag, e);
+        }
+    }
+    Ok((!exists).then_some(tag))
+}
+
+/// Push `tag_ref` to the push remote, unless the remote already has it.
+fn push_tag(repo_path: &std::path::PathBuf, tag_ref: &str) -> Result<()> {
+    let Some(refspec) = push_target(repo_path)?.and(push_refspec(repo_path)) else {
+        debug!("No push target, not pushing {}", tag_ref);
+        return Ok(());
+    };
+    let remote_tags = run_checked_command_in_dir(
+        repo_path,
+        "git",
+        &["ls-remote", "--tags", &refspec.remote, tag_ref],
+    )?;
+    if remote_tags.trim().is_empty() {
+        run_checked_command_with_timeout(
+            repo_path,
+            "git",
+            &["push", &refspec.remote, tag_ref],
+            config::Config::load()?.timeouts.push(),
+        )?;
+    }
+    Ok(())
+}
@@ -0,0 +1,87 @@
+use crate::COMMAND_NAME;
+use color_eyre::{eyre::eyre, Report, Result};
+use opentelemetry::sdk::trace::{self, Tracer};
+use opentelemetry::sdk::Resource;
+use opentelemetry::KeyValue;
+use opentelemetry_otlp::WithExportConfig;
+use tracing::Subscriber;
+use tracing_opentelemetry::OpenTelemetryLayer;
+use tracing_subscriber::registry::LookupSpan;
+
+/// A layer exporting spans over OTLP (gRPC) to `endpoint`, e.g.
+/// `http://localhost:4317`. Tracing is opt-in, so there is no layer without an
+/// endpoint.
+pub fn otlp_layer<S>(endpoint: Option<String>) -> Result<Option<OpenTelemetryLayer<S, Tracer>>>
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+{
+    let Some(endpoint) = endpoint else {
+        return Ok(None);
+    };
+    let tracer = opentelemetry_otlp::new_pipeline()
+        .tracing()
+        .with_exporter(
+            opentelemetry_otlp::new_exporter()
+                .tonic()
+                .with_endpoint(endpoint),
+        )
+        .with_trace_config(
+            trace::config().with_resource(Resource::new(vec![KeyValue::new(
+                "service.name",
+                COMMAND_NAME,
+            )])),
+        )
+        .install_batch(opentelemetry::runtime::Tokio)?;
+    Ok(Some(tracing_opentelemetry::layer().with_tracer(tracer)))
+}
+
+/// Report panics and failed runs to Sentry at `dsn`, until the guard is
+/// dropped. Crash reporting is opt-in, so nothing is sent without a DSN.
+pub fn init_sentry(
+    dsn: Option<String>,
+    environment: Option<String>,
+) -> Result<Option<sentry::ClientInitGuard>> {
+    let Some(dsn) = dsn else {
+        return Ok(None);
+    };
+    let dsn = dsn
+        .parse()
+        .map_err(|e| eyre!("Invalid Sentry DSN {}: {}", dsn, e))?;
+    Ok(Some(sentry::init(sentry::ClientOptions {
+        dsn: Some(dsn),
+        release: sentry::release_name!(),
+        environment: environment.map(Into::into),
+        attach_stacktrace: true,
+        ..Default::default()
+    })))
+}
+
+/// A layer keeping log lines as Sentry breadcrumbs, so a reported failure
+/// comes with what the run did before it.
+pub fn sentry_layer<S>() -> sentry::integrations::tracing::SentryLayer<S>
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+{
+    sentry::integrations::tracing::layer()
+        .event_filter(|_| sentry::integrations::tracing::EventFilter::Breadcrumb)
+}
+
+/// Send a failed run to Sentry, tagged with the repo and the phase that
+/// failed. Does nothing unless Sentry is set up.
+pub fn capture_failure(repo_path: &std::path::Path, phase: &str, error: &Report) {
+    sentry::with_scope(
+        |scope| {
+            scope.set_tag("repo", repo_path.display());
+            scope.set_tag("phase", phase);
+        },
+        || sentry::capture_message(&format!("{:#}", error), sentry::Level::Error),
+    );
+}
+
+/// Flush any spans and Sentry events that have not been sent yet.
+pub fn shutdown() {
+    opentelemetry::global::shutdown_tracer_provider();
+    if let Some(client) = sentry::Hub::current().client() {
+        client.flush(Some(std::time::Duration::from_secs(2)));
+    }
+}
@@ -0,0 +1,62 @@
+use crate::config::CommitConfig;
+use crate::run_checked_command_in_dir;
+use color_eyre::{eyre::eyre, Result};
+
+/// The repo's committer as `Name <email>`, as git would sign off with.
+fn committer(repo_path: &std::path::PathBuf) -> Result<String> {
+    // `git var` prints `Name <email> timestamp timezone`.
+    let ident = run_checked_command_in_dir(repo_path, "git", &["var", "GIT_COMMITTER_IDENT"])?;
+    let end = ident
+        .rfind('>')
+        .ok_or_else(|| eyre!("Unexpected committer identity {}", ident.trim()))?;
+    Ok(ident[..=end].to_string())
+}
+
+/// The trailers to append to an autocommit message.
+fn trailers(repo_path: &std::path::PathBuf, config: &CommitConfig) -> Result<Vec<String>> {
+    let mut trailers = Vec::new();
+    if !config.trailer.trim().is_empty() {
+        trailers.push(config.trailer.trim().to_string());
+    }
+    if config.version_trailer {
+        trailers.push(format!("Autocommit-Version: {}", env!("CARGO_PKG_VERSION")));
+    }
+    if let Some(co_author) = config.co_author.as_deref().filter(|c| !c.trim().is_empty()) {
+        trailers.push(format!("Co-authored-by: {}", co_author.trim()));
+    }
+    if config.signoff {
+        trailers.push(format!("Signed-off-by: {}", committer(repo_path)?));
+    }
+    Ok(trailers)
+}
+
+/// `message` with the configured trailers appended in a final paragraph,
+/// skipping any it already has.
+pub fn append(
+    repo_path: &std::path::PathBuf,
+    message: &str,
+    config: &CommitConfig,
+) -> Result<String> {
+    let message = message.trim_end();
+    let missing = trailers(repo_path, config)?
+        .into_iter()
+        .filter(|trailer| !message.lines().any(|line| line.trim() == trailer))
+        .collect::<Vec<_>>();
+    if missing.is_empty() {
+        return Ok(message.to_string());
+    }
+    Ok(format!("{}\n\n{}", message, missing.join("\n")))
+}
+
+/// Whether `message` carries the configured autocommit trailer.
+pub fn is_marked(message: &str, config: &CommitConfig) -> bool {
+    let Some((key, _)) = config.trailer.split_once(':') else {
+        return false;
+    };
+    let prefix = format!("{}:", key.trim());
+    // Trailers live in the last paragraph, never in the subject.
+    match message.trim_end().rsplit_once("\n\n") {
+        Some((_, last)) => last.lines().any(|line| line.starts_with(&prefix)),
+        None => false,
+    }
+}
@@ -0,0 +1,290 @@
+use crate::{list, repo_dirty, run_command_in_dir, write_autocommits, CronLine};
+use chrono::Local;
+use color_eyre::Result;
+use crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use crossterm::execute;
+use crossterm::terminal::{
+    disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen,
+};
+use ratatui::backend::CrosstermBackend;
+use ratatui::layout::{Constraint, Direction, Layout};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::widgets::{Block, Borders, Cell, Paragraph, Row, Table, TableState};
+use ratatui::Terminal;
+use std::process::{Command, Stdio};
+use std::time::Duration;
+
+const LOG_LINES: usize = 20;
+
+struct RepoStatus {
+    cron_line: CronLine,
+    /// `None` if the repo could not be inspected, e.g. it was moved.
+    dirty: Option<bool>,
+    last_run: String,
+    recent_commits: Vec<String>,
+}
+
+impl RepoStatus {
+    fn load(cron_line: CronLine) -> Self {
+        let path = std::path::PathBuf::from(cron_line.repo_path());
+        let dirty = repo_dirty(&path);
+        let last_run = tail_log(&cron_line, 1)
+            .pop()
+            .unwrap_or_else(|| "never".to_string());
+        let recent_commits =
+            run_command_in_dir(&path, "git", &["log", "-5", "--format=%h %cr  %s"])
+                .map(|log| log.lines().map(|l| l.to_string()).collect())
+                .unwrap_or_default();
+        Self {
+            cron_line,
+            dirty,
+            last_run,
+            recent_commits,
+        }
+    }
+}
+
+/// The last `count` non-empty lines of an autocommit's log.
+fn tail_log(cron_line: &CronLine, count: usize) -> Vec<String> {
+    let contents = cron_line
+        .log_path()
+        .ok()
+        .and_then(|log| std::fs::read_to_string(log).ok())
+        .unwrap_or_default();
+    let lines = contents
+        .lines()
+        .filter(|l| !l.trim().is_empty())
+        .map(|l| l.to_string())
+        .collect::<Vec<_>>();
+    lines[lines.len().saturating_sub(count)..].to_vec()
+}
+
+struct Dashboard {
+    repos: Vec<RepoStatus>,
+    table: TableState,
+    show_logs: bool,
+    message: String,
+}
+
+impl Dashboard {
+    fn load() -> Result<Self> {
+        let repos = list()?
+            .into_iter()
+            .map(RepoStatus::load)
+            .collect::<Vec<_>>();
+        let mut table = TableState::default();
+        if !repos.is_empty() {
+            table.select(Some(0));
+        }
+        Ok(Self {
+            repos,
+            table,
+            show_logs: false,
+            message: "r: run  p: pause/resume  l: logs  R: refresh  q: quit".to_string(),
+        })
+    }
+
+    fn refresh(&mut self) -> Result<()> {
+        let selected = self.table.selected();
+        let show_logs = self.show_logs;
+        let message = std::mem::take(&mut self.message);
+        *self = Self::load()?;
+        self.show_logs = show_logs;
+        self.message = message;
+        if let Some(selected) = selected {
+            if selected < self.repos.len() {
+                self.table.select(Some(selected));
+            }
+        }
+        Ok(())
+    }
+
+    fn selected(&self) -> Option<&RepoStatus> {
+        self.table.selected().and_then(|i| self.repos.get(i))
+    }
+
+    fn move_selection(&mut self, delta: isize) {
+        if self.repos.is_empty() {
+            return;
+        }
+        let current = self.table.selected().unwrap_or(0) as isize;
+        let next = (current + delta).rem_euclid(self.repos.len() as isize);
+        self.table.select(Some(next as usize));
+    }
+
+    /// Start the scheduled command for the selected repo in the background,
+    /// exactly as cron would.
+    fn trigger_run(&mut self) -> Result<()> {
+        let Some(repo) = self.selected() else {
+            return Ok(());
+        };
+        let path = repo.cron_line.repo_path().to_string();
+        Command::new("sh")
+            .arg("-c")
+            .arg(repo.cron_line.shell_command())
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()?;
+        self.message = format!("Started run on {}", path);
+        Ok(())
+    }
+
+    fn toggle_pause(&mut self) -> Result<()> {
+        let Some(repo) = self.selected() else {
+            return Ok(());
+        };
+        let path = repo.cron_line.repo_path().to_string();
+
+        // Re-read the crontab so edits made since the dashboard loaded survive.
+        let mut autocommits = list()?;
+        let mut paused = false;
+        for autocommit in autocommits.iter_mut() {
+            if autocommit.repo_path() == path {
+                autocommit.paused = !autocommit.paused;
+                paused = autocommit.paused;
+            }
+        }
+        write_autocommits(&autocommits)?;
+        self.refresh()?;
+        self.message = format!("{} {}", if paused { "Paused" } else { "Resumed" }, path);
+        Ok(())
+    }
+}
+
+/// Show every configured autocommit until the user quits.
+pub fn run_dashboard() -> Result<()> {
+    let mut dashboard = Dashboard::load()?;
+
+    enable_raw_mode()?;
+    let mut stdout = std::io::stdout();
+    execute!(stdout, EnterAlternateScreen)?;
+    let mut terminal = Terminal::new(CrosstermBackend::new(stdout))?;
+
+    let result = event_loop(&mut terminal, &mut dashboard);
+
+    disable_raw_mode()?;
+    execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
+    terminal.show_cursor()?;
+    result
+}
+
+fn event_loop(
+    terminal: &mut Terminal<CrosstermBackend<std::io::Stdout>>,
+    dashboard: &mut Dashboard,
+) -> Result<()> {
+    loop {
+        draw(terminal, dashboard)?;
+
+        if !event::poll(Duration::from_secs(5))? {
+            // Keep the dirty/last run columns current while idle.
+            dashboard.refresh()?;
+            continue;
+        }
+        let Event::Key(key) = event::read()? else {
+            continue;
+        };
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+        let result = match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+            KeyCode::Down | KeyCode::Char('j') => {
+                dashboard.move_selection(1);
+                Ok(())
+            }
+            KeyCode::Up | KeyCode::Char('k') => {
+                dashboard.move_selection(-1);
+                Ok(())
+            }
+            KeyCode::Char('r') => dashboard.trigger_run(),
+            KeyCode::Char('p') => dashboard.toggle_pause(),
+            KeyCode::Char('l') => {
+                dashboard.show_logs = !dashboard.show_logs;
+                Ok(())
+            }
+            KeyCode::Char('R') => dashboard.refresh(),
+            _ => Ok(()),
+        };
+        if let Err(e) = result {
+            dashboard.message = format!("Error: {}", e);
+        }
+    }
+}
+
+fn draw(
+    terminal: &mut Terminal<CrosstermBackend<std::io::Stdout>>,
+    dashboard: &mut Dashboard,
+) -> Result<()> {
+    let now = Local::now();
+    let rows = dashboard
+        .repos
+        .iter()
+        .map(|repo| {
+            let (status, color) = match (repo.cron_line.paused, repo.dirty) {
+                (true, _) => ("paused", Color::Yellow),
+                (false, None) => ("missing", Color::Red),
+                (false, Some(true)) => ("dirty", Color::Magenta),
+                (false, Some(false)) => ("clean", Color::Green),
+            };
+            let next_run = if repo.cron_line.paused {
+                "-".to_string()
+            } else {
+                repo.cron_line
+                    .next_run(now)
+                    .map(|next| next.format("%H:%M").to_string())
+                    .unwrap_or_else(|| repo.cron_line.frequency.join(" "))
+            };
+            Row::new(vec![
+                Cell::from(repo.cron_line.repo_path().to_string()),
+                Cell::from(status).style(Style::default().fg(color)),
+                Cell::from(next_run),
+                Cell::from(repo.last_run.clone()),
+            ])
+        })
+        .collect::<Vec<_>>();
+
+    let details = match dashboard.selected() {
+        Some(repo) if dashboard.show_logs => tail_log(&repo.cron_line, LOG_LINES).join("\n"),
+        Some(repo) => repo.recent_commits.join("\n"),
+        None => "No autocommits configured, use `autocommit create`".to_string(),
+    };
+    let details_title = if dashboard.show_logs {
+        "Log"
+    } else {
+        "Recent commits"
+    };
+
+    terminal.draw(|frame| {
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Percentage(50),
+                Constraint::Min(5),
+                Constraint::Length(1),
+            ])
+            .split(frame.size());
+
+        let table = Table::new(rows)
+            .header(
+                Row::new(vec!["Repo", "Status", "Next run", "Last log line"])
+                    .style(Style::default().add_modifier(Modifier::BOLD)),
+            )
+            .block(Block::default().borders(Borders::ALL).title("Autocommits"))
+            .widths(&[
+                Constraint::Percentage(35),
+                Constraint::Length(8),
+                Constraint::Length(10),
+                Constraint::Percentage(50),
+            ])
+            .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(table, chunks[0], &mut dashboard.table);
+
+        let details = Paragraph::new(details)
+            .block(Block::default().borders(Borders::ALL).title(details_title));
+        frame.render_widget(details, chunks[1]);
+
+        frame.render_widget(Paragraph::new(dashboard.message.clone()), chunks[2]);
+    })?;
+    Ok(())
+}
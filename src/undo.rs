@@ -0,0 +1,86 @@
+use crate::{
+    config, history, is_pushed, push_refspec, run_checked_command_in_dir,
+    run_checked_command_with_timeout, state,
+};
+use color_eyre::{eyre::eyre, Result};
+use tracing::info;
+
+/// Remove the most recent autocommit with a soft reset, keeping its changes
+/// staged in the working tree. Returns the removed commit.
+pub fn undo(repo_path: &std::path::PathBuf, force_with_lease: bool) -> Result<String> {
+    let state = state::State::load()?;
+    let head = run_checked_command_in_dir(repo_path, "git", &["rev-parse", "HEAD"])?
+        .trim()
+        .to_string();
+    let head_message = run_checked_command_in_dir(repo_path, "git", &["log", "-1", "--format=%B"])?;
+    let config = config::Config::load()?;
+    let commit_config = config.commit;
+    let repo_state = state.get(repo_path);
+    let last_autocommit = repo_state.and_then(|repo_state| repo_state.autocommits.last().cloned());
+    match last_autocommit {
+        _ if history::is_autocommit(repo_state, &commit_config, &head, &head_message) => {}
+        Some(sha) if is_ancestor(repo_path, &sha) => {
+            return Err(eyre!(
+                "The last autocommit {} has commits on top of it, refusing to undo",
+                &sha[..sha.len().min(12)]
+            ));
+        }
+        _ => return Err(eyre!("HEAD is not an autocommit, nothing to undo")),
+    }
+    // Root commits have no parent to reset to.
+    run_checked_command_in_dir(repo_path, "git", &["rev-parse", "--verify", "-q", "HEAD~1"])
+        .map_err(|_| eyre!("HEAD is the first commit of the repo, refusing to undo"))?;
+
+    let pushed = is_pushed(repo_path, &head)?;
+    if pushed && !force_with_lease {
+        return Err(eyre!(
+            "The autocommit was already pushed, use --force-with-lease to undo it and force push"
+        ));
+    }
+
+    run_checked_command_in_dir(repo_path, "git", &["reset", "--soft", "HEAD~1"])?;
+    info!("Removed autocommit {}", head);
+    if pushed {
+        // Only overwrite the remote if it still points at the removed commit.
+        let refspec = push_refspec(repo_path)
+            .ok_or_else(|| eyre!("No push target configured, not pushing"))?;
+        run_checked_command_with_timeout(
+            repo_path,
+            "git",
+            &[
+                "push",
+                &format!("--force-with-lease={}:{}", refspec.dst, head),
+                &refspec.remote,
+                &format!("{}:{}", refspec.src, refspec.dst),
+            ],
+            config.timeouts.push(),
+        )?;
+    }
+
+    let new_head = run_checked_command_in_dir(repo_path, "git", &["rev-parse", "HEAD"])?
+        .trim()
+        .to_string();
+    state::State::update(|state| {
+        let repo_state = state.repo(repo_path);
+        repo_state.autocommits.retain(|sha| *sha != head);
+        repo_state.last_commit = repo_state
+            .autocommits
+            .last()
+            .filter(|sha| **sha == new_head)
+            .cloned();
+        if repo_state.last_commit.is_none() {
+            repo_state.last_message = None;
+        }
+        Ok(())
+    })?;
+    Ok(head)
+}
+
+fn is_ancestor(repo_path: &std::path::PathBuf, rev: &str) -> bool {
+    run_checked_command_in_dir(
+        repo_path,
+        "git",
+        &["merge-base", "--is-ancestor", rev, "HEAD"],
+    )
+    .is_ok()
+}
@@ -0,0 +1,183 @@
+use crate::history::is_autocommit;
+use crate::{
+    config, push_branch, push_refspec, run_checked_command_in_dir,
+    run_checked_command_with_timeout, state,
+};
+use color_eyre::Result;
+use serde::Serialize;
+use std::collections::BTreeSet;
+use tracing::{info, warn};
+
+/// Whether a repo's recent autocommits made it to its remote.
+#[derive(Debug, Serialize)]
+pub struct Verification {
+    pub repo: String,
+    pub remote: String,
+    /// Autocommits no branch on the remote contains, newest first.
+    pub unpushed: Vec<String>,
+    /// Autocommits recorded in the state file that no longer exist locally,
+    /// e.g. because they were squashed.
+    pub missing: Vec<String>,
+    /// Local branches pushed again with `--repush`.
+    pub repushed: Vec<String>,
+    /// Why the remote could not be fetched, the other fields then go by
+    /// the last fetch.
+    pub fetch_error: Option<String>,
+}
+
+impl std::fmt::Display for Verification {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: ", self.repo)?;
+        if self.unpushed.is_empty() {
+            write!(f, "all autocommits on {}", self.remote)?;
+        } else {
+            write!(
+                f,
+                "{} autocommits not on {}:",
+                self.unpushed.len(),
+                self.remote
+            )?;
+            for sha in &self.unpushed {
+                write!(f, " {}", &sha[..sha.len().min(10)])?;
+            }
+        }
+        if !self.missing.is_empty() {
+            write!(f, ", {} no longer exist locally", self.missing.len())?;
+        }
+        if !self.repushed.is_empty() {
+            write!(f, ", pushed {} again", self.repushed.join(", "))?;
+        }
+        if let Some(error) = &self.fetch_error {
+            write!(f, " (fetching {} failed: {})", self.remote, error)?;
+        }
+        Ok(())
+    }
+}
+
+/// The refs under `prefix` (e.g. `refs/remotes/origin`) that contain `sha`.
+fn refs_containing(repo_path: &std::path::PathBuf, sha: &str, prefix: &str) -> Result<Vec<String>> {
+    let refs = run_checked_command_in_dir(
+        repo_path,
+        "git",
+        &[
+            "for-each-ref",
+            "--contains",
+            sha,
+            "--format=%(refname)",
+            prefix,
+        ],
+    )?;
+    Ok(refs.lines().map(|r| r.to_string()).collect())
+}
+
+/// Autocommits among the last `limit` commits on HEAD or a snapshot branch
+/// that no branch on `remote` contains, newest first. Autocommits are the
+/// ones in the state file or marked with the trailer, so commits whose push
+/// failed are found however the run that made them ended.
+fn unpushed_autocommits(
+    repo_path: &std::path::PathBuf,
+    repo_state: Option<&state::RepoState>,
+    commit_config: &config::CommitConfig,
+    remote: &str,
+    limit: usize,
+) -> Result<Vec<String>> {
+    // Records are separated by \x1e, the message follows \x1f.
+    let log = run_checked_command_in_dir(
+        repo_path,
+        "git",
+        &[
+            "log",
+            &format!("--max-count={}", limit),
+            "--format=%H%x1f%B%x1e",
+            "HEAD",
+            "--branches=autocommit/*",
+            "--not",
+            &format!("--remotes={}", remote),
+        ],
+    )?;
+    Ok(log
+        .split('\x1e')
+        .filter_map(|record| record.trim_start().split_once('\x1f'))
+        .filter(|(sha, message)| is_autocommit(repo_state, commit_config, sha, message))
+        .map(|(sha, _)| sha.to_string())
+        .collect())
+}
+
+/// Check that the autocommits among the last `limit` local commits,
+/// snapshots included, are contained in a branch on the push remote, after
+/// fetching it. With `repush`, push the local branches holding the ones that
+/// are not again and check once more.
+pub fn verify(repo_path: &std::path::PathBuf, limit: usize, repush: bool) -> Result<Verification> {
+    let state = state::State::load()?;
+    let repo_state = state.get(repo_path);
+    let config = config::Config::load()?;
+    let refspec = push_refspec(repo_path);
+    let remote = refspec
+        .as_ref()
+        .map_or_else(|| "origin".to_string(), |r| r.remote.clone());
+    let push_timeout = config.timeouts.push();
+
+    let fetch_error = run_checked_command_with_timeout(
+        repo_path,
+        "git",
+        &["fetch", "--quiet", &remote],
+        push_timeout,
+    )
+    .err()
+    .map(|e| format!("{:#}", e));
+
+    let mut unpushed = unpushed_autocommits(repo_path, repo_state, &config.commit, &remote, limit)?;
+    let missing = repo_state
+        .map_or(&[][..], |s| &s.autocommits[..])
+        .iter()
+        .rev()
+        .take(limit)
+        .filter(|sha| {
+            let commit = format!("{}^{{commit}}", sha);
+            run_checked_command_in_dir(repo_path, "git", &["cat-file", "-e", &commit]).is_err()
+        })
+        .cloned()
+        .collect();
+
+    let mut repushed = Vec::new();
+    if repush && !unpushed.is_empty() {
+        let mut branches = BTreeSet::new();
+        for sha in &unpushed {
+            branches.extend(refs_containing(repo_path, sha, "refs/heads")?);
+        }
+        for branch in branches {
+            info!("Pushing {} to {}", branch, remote);
+            // The checked-out branch goes where runs push it, others (like
+            // snapshot branches) to the same name.
+            if refspec.as_ref().map_or(false, |r| r.src == branch) {
+                push_branch(repo_path, false, push_timeout)?;
+            } else {
+                run_checked_command_with_timeout(
+                    repo_path,
+                    "git",
+                    &["push", &remote, &format!("{}:{}", branch, branch)],
+                    push_timeout,
+                )?;
+            }
+            repushed.push(branch.trim_start_matches("refs/heads/").to_string());
+        }
+        unpushed = unpushed_autocommits(repo_path, repo_state, &config.commit, &remote, limit)?;
+    }
+    if !unpushed.is_empty() {
+        warn!(
+            repo = %repo_path.display(),
+            "{} autocommits are not on {}",
+            unpushed.len(),
+            remote
+        );
+    }
+
+    Ok(Verification {
+        repo: repo_path.display().to_string(),
+        remote,
+        unpushed,
+        missing,
+        repushed,
+        fetch_error,
+    })
+}